@@ -1,11 +1,13 @@
 use rsgrad_nn::loss::L2loss;
 use rsgrad_primitive::tensor::Tensor;
+use rsgrad_primitive::tensor;
+use rsgrad_primitive::grad_mode;
+use rsgrad_primitive::random;
 use rsgrad_nn::layer::ReLU;
 use rsgrad_nn::layer::Linear;
 use rsgrad_nn::optimizer::SGD;
 use std::rc::Rc;
 use std::cell::RefCell;
-use rand::prelude::*;
 
 struct NeuralNet {
     layer_1: Linear,
@@ -37,24 +39,15 @@ impl NeuralNet {
 }
 
 fn main() {
-    let mut rng = rand::thread_rng();
+    // a fixed seed makes both the initial weights and the data the same on every run
+    random::manual_seed(0);
     let model = NeuralNet::new();
     let optim = SGD::new(model.params(),0.001);
-    let mut init_grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 1])));
+    let mut init_grad = Rc::new(RefCell::new(Tensor::scalar(1.0)));
     let loss = L2loss{};
-    let mut train_data: Vec<(f32, f32)> = Vec::new();
-    for n in 0..3000 {
-        let a: f32 = 2.0*rng.gen::<f32>();
-        let b: f32 = 2.0*rng.gen::<f32>();
-        train_data.push((a, b));
-    }
+    let train_data: Vec<(f32, f32)> = random::uniform::<f32>(0.0, 2.0, &[3000, 2]).values().chunks(2).map(|pair| (pair[0], pair[1])).collect();
 
-    let mut val_data: Vec<(f32, f32)> = Vec::new();
-    for n in 0..3000 {
-        let a: f32 = 2.0*rng.gen::<f32>();
-        let b: f32 = 2.0*rng.gen::<f32>();
-        val_data.push((a, b));
-    }
+    let val_data: Vec<(f32, f32)> = random::uniform::<f32>(0.0, 2.0, &[3000, 2]).values().chunks(2).map(|pair| (pair[0], pair[1])).collect();
 
     for epoch in 0..100 {
         let mut running_loss: f32 = 0.0;
@@ -62,10 +55,8 @@ fn main() {
             let a: f32 = train_data[n].0;
             let b: f32 = train_data[n].1;
             let c: f32 = a.exp()+b.exp();
-            let mut x: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 2])));
-            let mut t: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(Tensor::constant_fill(c, &[1,1])));
-            *x.borrow_mut().at(&[0,0]) = a;
-            *x.borrow_mut().at(&[0,1]) = b;
+            let mut x: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(tensor![[a, b]].with_requires_grad(false)));
+            let mut t: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(Tensor::constant_fill(c, &[1,1]).with_requires_grad(false)));
             optim.zero_grad();
             let mut res = model.forward(x);
             let mut loss_val = loss.forward(res.clone(), t);
@@ -78,20 +69,19 @@ fn main() {
         }
     }
 
+    let _guard = grad_mode::no_grad_guard();
     let mut val_running_loss = 0.0;
     for n in 0..3000 {
         let a: f32 = val_data[n].0;
         let b: f32 = val_data[n].1;
         let c: f32 = a.exp()+b.exp();
-        let mut x: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 2])));
+        let mut x: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(tensor![[a, b]]));
         let mut t: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(Tensor::constant_fill(c, &[1,1])));
-        *x.borrow_mut().at(&[0,0]) = a;
-        *x.borrow_mut().at(&[0,1]) = b;
         let mut res = model.forward(x);
         let mut loss_val = loss.forward(res.clone(), t);
         val_running_loss += loss_val.buffer[0];
         if n%1000==0 {
-            println!("n:{} expected: {} got: {}", n, c, res.borrow().buffer[0]);
+            println!("n:{} expected: {} got: {}", n, c, res.borrow());
         }
     }
     println!("Val running loss: {}", val_running_loss/3000.0);
@@ -1,5 +1,6 @@
 use rsgrad_primitive::tensor::Tensor;
 use rsgrad_primitive::ops;
+use rsgrad_primitive::random;
 use std::cell::RefCell;
 use std::rc::Rc;
 
@@ -8,8 +9,11 @@ pub struct Linear {
 }
 
 impl Linear {
+    /// Draws the weights uniformly from [-1/sqrt(in_shape), 1/sqrt(in_shape)) with the global
+    /// generator, so outputs start out centered around zero whatever the input size.
     pub fn new(in_shape: u32, out_shape: u32) -> Linear {
-        let mut param = Rc::new(RefCell::new(Tensor::rand(&[in_shape, out_shape])));
+        let bound = 1.0/(in_shape as f64).sqrt();
+        let mut param = Rc::new(RefCell::new(random::uniform(-bound, bound, &[in_shape, out_shape])));
         Linear {param: param}
     }
 
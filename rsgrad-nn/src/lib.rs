@@ -17,6 +17,14 @@ pub fn add(left: usize, right: usize) -> usize {
 mod tests {
     use super::*;
 
+    #[test]
+    fn linear_init_test() {
+        let weights = Linear::new(16, 64).param.borrow().values().into_owned();
+        assert!(weights.iter().all(|&weight| (-0.25..0.25).contains(&weight)));
+        assert!(weights.iter().any(|&weight| weight < 0.0));
+        assert!(weights.iter().any(|&weight| weight > 0.0));
+    }
+
     #[test]
     fn it_works() {
         let result = add(2, 2);
@@ -26,7 +34,7 @@ mod tests {
     #[test]
     fn linear_forward_test() {
         let linear_layer = Linear::new(3, 6);
-        let x = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 3])));
+        let x = Rc::new(RefCell::new(Tensor::ones(&[1, 3])));
         let res = linear_layer.forward(x);
         assert_eq!(linear_layer.param.borrow().shape(), &[3, 6]);
         assert_eq!(res.borrow().shape(), &[1, 6]);
@@ -35,11 +43,11 @@ mod tests {
     #[test]
     fn linear_backward_test() {
         let linear_layer = Linear::new(3, 6);
-        let x = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 3])));
+        let x = Rc::new(RefCell::new(Tensor::ones(&[1, 3])));
         let mut res = linear_layer.forward(x.clone());
         assert_eq!(linear_layer.param.borrow().shape(), &[3, 6]);
         assert_eq!(res.borrow().shape(), &[1, 6]);
-        let mut init_grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 6])));
+        let mut init_grad = Rc::new(RefCell::new(Tensor::ones(&[1, 6])));
         res.borrow_mut().backward(init_grad);
 
         let mut weights = linear_layer.param.borrow_mut();
@@ -50,13 +58,14 @@ mod tests {
     #[test]
     fn relu_backward_test() {
         let linear_layer = Linear::new(3, 6);
+        *linear_layer.param.borrow_mut() = Tensor::ones(&[3, 6]);
         let activation = ReLU;
-        let x = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 3])));
+        let x = Rc::new(RefCell::new(Tensor::ones(&[1, 3])));
         let mut res_1 = linear_layer.forward(x.clone());
         let mut res_2 = activation.forward(res_1.clone());
         assert_eq!(linear_layer.param.borrow().shape(), &[3, 6]);
         assert_eq!(res_2.borrow().shape(), &[1, 6]);
-        let mut init_grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 6])));
+        let mut init_grad = Rc::new(RefCell::new(Tensor::ones(&[1, 6])));
         res_2.borrow_mut().backward(init_grad);
 
         let mut weights = linear_layer.param.borrow_mut();
@@ -66,23 +75,24 @@ mod tests {
     #[test]
     fn relu_backward_test_negative() {
         let linear_layer = Linear::new(3, 6);
+        *linear_layer.param.borrow_mut() = Tensor::constant_fill(-1.0, &[3, 6]);
         let activation = ReLU;
-        let x = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 3])));
+        let x = Rc::new(RefCell::new(Tensor::ones(&[1, 3])));
         let mut res_1 = linear_layer.forward(x.clone());
         let mut res_2 = activation.forward(res_1.clone());
         assert_eq!(linear_layer.param.borrow().shape(), &[3, 6]);
         assert_eq!(res_2.borrow().shape(), &[1, 6]);
-        let mut init_grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 6])));
+        let mut init_grad = Rc::new(RefCell::new(Tensor::ones(&[1, 6])));
         res_2.borrow_mut().backward(init_grad);
 
         let mut weights = linear_layer.param.borrow_mut();
-        assert_eq!(*(weights.grad.as_ref().unwrap().borrow()).at_im(&[1, 1]), 1.0);
+        assert_eq!(*(weights.grad.as_ref().unwrap().borrow()).at_im(&[1, 1]), 0.0);
     }
 
     #[test]
     fn L2loss_test() {
         let activation = ReLU;
-        let x = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 3])));
+        let x = Rc::new(RefCell::new(Tensor::ones(&[1, 3])));
         let t = Rc::new(RefCell::new(Tensor::constant_fill(3.0, &[1, 3])));
         let mut res_2 = activation.forward(x.clone());
         let mut res_3 = L2loss.forward(res_2.clone(), t);
@@ -92,14 +102,14 @@ mod tests {
     #[test]
     fn SGD_test() {
         let activation = ReLU;
-        let x = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 3])));
+        let x = Rc::new(RefCell::new(Tensor::ones(&[1, 3])));
         let t = Rc::new(RefCell::new(Tensor::constant_fill(3.0, &[1, 3])));
         let mut res_2 = activation.forward(x.clone());
         let mut res_3 = L2loss.forward(res_2.clone(), t.clone());
         let mut params: Vec<Rc<RefCell<Tensor>>> = Vec::new();
         params.push(res_2.clone());
         let mut optim = SGD::new(params, 0.01);
-        let mut init_grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1])));
+        let mut init_grad = Rc::new(RefCell::new(Tensor::scalar(1.0)));
         res_3.backward(init_grad);
         assert_eq!(res_2.borrow().grad.as_ref().unwrap().borrow().buffer[0], -4.0);
         optim.step();
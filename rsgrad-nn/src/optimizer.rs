@@ -15,10 +15,11 @@ impl SGD {
 
     pub fn step(&self) {
         for param in &self.params {
-            let size = param.borrow().buffer.len();
-            for i in 0..size {
-                let step_size = self.lr * param.borrow().grad.as_ref().unwrap().borrow().buffer[i];
-                param.borrow_mut().buffer[i] -= step_size;
+            let grad = param.borrow().grad.clone().unwrap();
+            let grad = grad.borrow();
+            let mut param = param.borrow_mut();
+            for (offset, step) in param.offsets().into_iter().zip(grad.values().iter()) {
+                param.buffer[offset] -= self.lr * step;
             }
         }
     }
@@ -0,0 +1,53 @@
+//! Times square matrix products with the blocked kernel behind `MatMul` against the element by
+//! element loop it replaced. Run with `cargo bench --bench matmul`, adding
+//! `--features parallel` to spread the kernel over all cores.
+use rsgrad_primitive::ops::MatMul;
+use rsgrad_primitive::random::Generator;
+use rsgrad_primitive::tensor::Tensor;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// The previous `MatMul::forward`: one indexed read of each operand per multiply-add.
+fn reference(a: &Tensor, b: &Tensor) -> Tensor {
+    let (n, k, m) = (a.shape[0], a.shape[1], b.shape[1]);
+    let mut result = Tensor::zeros(&[n, m]);
+    for i in 0..n {
+        for j in 0..m {
+            let mut current = 0.0;
+            for p in 0..k {
+                current += a.at_im(&[i, p]) * b.at_im(&[p, j]);
+            }
+            *result.at(&[i, j]) = current;
+        }
+    }
+    result
+}
+
+/// Best of a few runs, which is less noisy than the mean on a shared machine.
+fn time(runs: u32, mut f: impl FnMut()) -> Duration {
+    (0..runs).map(|_| {
+        let start = Instant::now();
+        f();
+        start.elapsed()
+    }).min().unwrap()
+}
+
+fn main() {
+    let mut generator = Generator::new(0);
+    for size in [64, 128, 256, 512] {
+        let a = Rc::new(RefCell::new(generator.normal::<f32>(0.0, 1.0, &[size, size])));
+        let b = Rc::new(RefCell::new(generator.normal::<f32>(0.0, 1.0, &[size, size])));
+        let blocked = MatMul::forward_nograd(a.clone(), b.clone());
+        let expected = reference(&a.borrow(), &b.borrow());
+        let error = blocked.values().iter().zip(expected.values().iter()).map(|(x, y)| (x - y).abs()).fold(0.0, f32::max);
+        assert!(error < 1e-3, "kernels disagree by {}", error);
+
+        let runs = if size >= 512 {3} else {10};
+        let naive = time(runs, || {reference(&a.borrow(), &b.borrow());});
+        let fast = time(runs, || {MatMul::forward_nograd(a.clone(), b.clone());});
+        let gflops = |duration: Duration| 2.0*(size as f64).powi(3)/duration.as_secs_f64()/1e9;
+        println!("{0}x{0}: reference {1:>10.3?} ({2:>6.2} GFLOP/s), blocked {3:>10.3?} ({4:>6.2} GFLOP/s), {5:.1}x faster",
+            size, naive, gflops(naive), fast, gflops(fast), naive.as_secs_f64()/fast.as_secs_f64());
+    }
+}
@@ -0,0 +1,15 @@
+// With the `blas` feature the crate calls the CBLAS interface directly, so the library providing
+// it has to be linked. `RSGRAD_BLAS_LIB` picks it, e.g. `blis` or `cblas`, and
+// `RSGRAD_BLAS_DIR` adds a directory to the search path.
+fn main() {
+    println!("cargo:rerun-if-env-changed=RSGRAD_BLAS_LIB");
+    println!("cargo:rerun-if-env-changed=RSGRAD_BLAS_DIR");
+    if std::env::var_os("CARGO_FEATURE_BLAS").is_none() {
+        return
+    }
+    if let Ok(dir) = std::env::var("RSGRAD_BLAS_DIR") {
+        println!("cargo:rustc-link-search=native={}", dir);
+    }
+    let lib = std::env::var("RSGRAD_BLAS_LIB").unwrap_or_else(|_| "openblas".to_string());
+    println!("cargo:rustc-link-lib={}", lib);
+}
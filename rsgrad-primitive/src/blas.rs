@@ -0,0 +1,40 @@
+use std::any::TypeId;
+use std::os::raw::c_int;
+use crate::element::Float;
+
+const ROW_MAJOR: c_int = 101;
+const NO_TRANS: c_int = 111;
+
+// The CBLAS interface shared by OpenBLAS, BLIS, MKL and the reference implementation. The
+// build script links the library named by `RSGRAD_BLAS_LIB`, `openblas` by default.
+extern "C" {
+    fn cblas_sgemm(layout: c_int, trans_a: c_int, trans_b: c_int, m: c_int, n: c_int, k: c_int,
+        alpha: f32, a: *const f32, lda: c_int, b: *const f32, ldb: c_int, beta: f32, c: *mut f32, ldc: c_int);
+    fn cblas_dgemm(layout: c_int, trans_a: c_int, trans_b: c_int, m: c_int, n: c_int, k: c_int,
+        alpha: f64, a: *const f64, lda: c_int, b: *const f64, ldb: c_int, beta: f64, c: *mut f64, ldc: c_int);
+}
+
+/// Hands `out += a b` to the system BLAS when `T` is `f32` or `f64` and the sizes fit its
+/// integer type, returning whether it did. Everything else is left to the native kernel.
+pub fn gemm<T: Float>(a: &[T], b: &[T], out: &mut [T], n: usize, k: usize, m: usize) -> bool {
+    let (Ok(n), Ok(k), Ok(m)) = (c_int::try_from(n), c_int::try_from(k), c_int::try_from(m)) else {
+        return false
+    };
+    // the leading dimensions have to be at least 1 even for empty matrices
+    let (lda, ldb, ldc) = (k.max(1), m.max(1), m.max(1));
+    // SAFETY: the type ids guarantee `T` is exactly the float type the pointers are cast to, and
+    // the caller checked that the buffers hold `n*k`, `k*m` and `n*m` elements.
+    unsafe {
+        if TypeId::of::<T>() == TypeId::of::<f32>() {
+            cblas_sgemm(ROW_MAJOR, NO_TRANS, NO_TRANS, n, m, k, 1.0, a.as_ptr() as *const f32, lda,
+                b.as_ptr() as *const f32, ldb, 1.0, out.as_mut_ptr() as *mut f32, ldc);
+            return true
+        }
+        if TypeId::of::<T>() == TypeId::of::<f64>() {
+            cblas_dgemm(ROW_MAJOR, NO_TRANS, NO_TRANS, n, m, k, 1.0, a.as_ptr() as *const f64, lda,
+                b.as_ptr() as *const f64, ldb, 1.0, out.as_mut_ptr() as *mut f64, ldc);
+            return true
+        }
+    }
+    false
+}
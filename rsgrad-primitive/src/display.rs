@@ -0,0 +1,136 @@
+use std::cell::Cell;
+use std::fmt;
+use crate::element::Element;
+use crate::tensor::Tensor;
+
+/// How [`Tensor`]s are rendered by `Display`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrintOptions {
+    /// Digits after the decimal point for float elements. A precision given in the format
+    /// string, as in `{:.2}`, takes priority.
+    pub precision: usize,
+    /// Tensors with more elements than this are summarized.
+    pub threshold: usize,
+    /// How many entries a summarized dimension keeps at either end around the `...`.
+    pub edge_items: usize
+}
+
+impl Default for PrintOptions {
+    fn default() -> PrintOptions {
+        PrintOptions {precision: 4, threshold: 1000, edge_items: 3}
+    }
+}
+
+thread_local! {
+    static PRINT_OPTIONS: Cell<PrintOptions> = Cell::new(PrintOptions::default());
+}
+
+/// The options tensors are currently printed with on this thread.
+pub fn print_options() -> PrintOptions {
+    PRINT_OPTIONS.with(|options| options.get())
+}
+
+/// Changes how tensors are printed on this thread from now on.
+pub fn set_print_options(options: PrintOptions) {
+    PRINT_OPTIONS.with(|current| current.set(options));
+}
+
+const PREFIX: &str = "tensor(";
+
+/// The indices of a dimension of `size` that are printed, `None` standing for the `...`.
+fn shown(size: u32, summarize: bool, edge_items: usize) -> Vec<Option<usize>> {
+    let size = size as usize;
+    if summarize && size > 2*edge_items {
+        (0..edge_items).map(Some).chain([None]).chain((size-edge_items..size).map(Some)).collect()
+    } else {
+        (0..size).map(Some).collect()
+    }
+}
+
+struct Layout<'a> {
+    shape: &'a [u32],
+    stride: Vec<usize>,
+    summarize: bool,
+    edge_items: usize
+}
+
+impl Layout<'_> {
+    /// Flat indices, into the row-major values, of every printed element.
+    fn leaves(&self, dim: usize, base: usize, leaves: &mut Vec<usize>) {
+        if dim == self.shape.len() {
+            leaves.push(base);
+            return
+        }
+        for index in shown(self.shape[dim], self.summarize, self.edge_items).into_iter().flatten() {
+            self.leaves(dim + 1, base + index*self.stride[dim], leaves);
+        }
+    }
+
+    fn render(&self, dim: usize, base: usize, cell: &dyn Fn(usize) -> String, out: &mut String) {
+        let ndim = self.shape.len();
+        if dim == ndim {
+            out.push_str(&cell(base));
+            return
+        }
+        out.push('[');
+        for (position, index) in shown(self.shape[dim], self.summarize, self.edge_items).into_iter().enumerate() {
+            if position > 0 {
+                out.push(',');
+                if dim + 1 == ndim {
+                    out.push(' ');
+                } else {
+                    // rows go on their own line, every further dimension adds a blank line
+                    out.push_str(&"\n".repeat(ndim - dim - 1));
+                    out.push_str(&" ".repeat(PREFIX.len() + dim + 1));
+                }
+            }
+            match index {
+                Some(index) => self.render(dim + 1, base + index*self.stride[dim], cell, out),
+                None => out.push_str("...")
+            }
+        }
+        out.push(']');
+    }
+}
+
+/// Prints the elements in nested brackets, one bracket per dimension, right-aligned to a common
+/// width and followed by the shape and dtype. Large tensors are summarized with `...`, see
+/// [`PrintOptions`].
+impl<T: Element> fmt::Display for Tensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = print_options();
+        let precision = f.precision().unwrap_or(options.precision);
+        let values = self.values();
+        let mut stride = vec![1; self.ndim()];
+        for dim in (0..self.ndim().saturating_sub(1)).rev() {
+            stride[dim] = stride[dim + 1]*self.shape[dim + 1] as usize;
+        }
+        let layout = Layout {shape: &self.shape, stride, summarize: self.numel() > options.threshold, edge_items: options.edge_items};
+        let text = |index: usize| {
+            let value = values[index];
+            if T::DTYPE.is_float() {format!("{:.*}", precision, value.into_f64())} else {format!("{:?}", value)}
+        };
+        let mut leaves = Vec::new();
+        layout.leaves(0, 0, &mut leaves);
+        let width = leaves.iter().map(|&index| text(index).len()).max().unwrap_or(0);
+        let mut body = String::new();
+        layout.render(0, 0, &|index| format!("{:>width$}", text(index), width = width), &mut body);
+        write!(f, "{}{}, shape={:?}, dtype={})", PREFIX, body, self.shape, T::DTYPE)
+    }
+}
+
+/// Shows the layout and graph bookkeeping of a tensor rather than its values.
+impl<T: Element> fmt::Debug for Tensor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tensor")
+            .field("shape", &self.shape)
+            .field("stride", &self.stride)
+            .field("offset", &self.offset)
+            .field("dtype", &format_args!("{}", T::DTYPE))
+            .field("op", &self.op.as_ref().map(|op| op.name()))
+            .field("children", &self.children.len())
+            .field("grad", &self.grad.is_some())
+            .field("requires_grad", &self.requires_grad)
+            .finish()
+    }
+}
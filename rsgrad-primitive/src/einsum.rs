@@ -0,0 +1,166 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use crate::element::Float;
+use crate::error::{RsgradError, Result, or_panic};
+use crate::ops::{MatMul, Mult, Permute, Reshape, SumDim};
+use crate::tensor::Tensor;
+
+/// An operand together with the label of each of its dimensions.
+struct Operand<T: Float> {
+    tensor: Rc<RefCell<Tensor<T>>>,
+    labels: Vec<char>
+}
+
+/// Contracts `operands` as described by `equation`, e.g. `"bij,bjk->bik"` for a batched
+/// matrix product or `"i,ij,j->"` for a bilinear form. Every operand gets one letter per
+/// dimension; letters shared between operands have to agree in size and are multiplied
+/// together, letters missing from the output after `->` are summed over, and a letter repeated
+/// within one operand takes its diagonal. Without `->` the output holds the letters that appear
+/// once, in alphabetical order. Ellipses are not supported.
+///
+/// The operands are contracted pairwise from left to right with a batched [`MatMul`], so the
+/// result is built from recording ops and differentiates with respect to every operand.
+pub fn einsum<T: Float>(equation: &str, operands: Vec<Rc<RefCell<Tensor<T>>>>) -> Tensor<T> {
+    or_panic(try_einsum(equation, operands))
+}
+
+pub fn try_einsum<T: Float>(equation: &str, operands: Vec<Rc<RefCell<Tensor<T>>>>) -> Result<Tensor<T>> {
+    let (inputs, output) = parse(equation)?;
+    if inputs.len() != operands.len() {
+        return Err(RsgradError::invalid("einsum", &format!("{} operands for {} subscripts", operands.len(), inputs.len())))
+    }
+    let mut sizes: HashMap<char, u32> = HashMap::new();
+    for (labels, tensor) in inputs.iter().zip(operands.iter()) {
+        let tensor = tensor.borrow();
+        if labels.len() != tensor.ndim() {
+            return Err(RsgradError::rank("einsum", labels.len(), tensor.ndim()))
+        }
+        for (&label, &size) in labels.iter().zip(tensor.shape.iter()) {
+            let expected = *sizes.entry(label).or_insert(size);
+            if expected != size {
+                return Err(RsgradError::shape("einsum", &[expected], &[size]))
+            }
+        }
+    }
+    if let Some(&label) = output.iter().find(|label| !sizes.contains_key(label)) {
+        return Err(RsgradError::invalid("einsum", &format!("output label {} appears in no operand", label)))
+    }
+
+    let mut pending: Vec<Operand<T>> = operands.into_iter().zip(inputs).map(|(tensor, labels)| diagonal(Operand {tensor, labels}, &sizes)).collect();
+    pending.reverse();
+    let mut result = pending.pop().unwrap();
+    loop {
+        // labels still needed once the current operands are combined
+        let mut needed: HashSet<char> = output.iter().copied().collect();
+        needed.extend(pending.iter().flat_map(|operand| operand.labels.iter().copied()));
+        match pending.pop() {
+            Some(next) => {
+                let needed_by_pair: HashSet<char> = needed.iter().chain(next.labels.iter()).copied().collect();
+                let left = sum_unneeded(result, &needed_by_pair);
+                let needed_by_left: HashSet<char> = needed.iter().chain(left.labels.iter()).copied().collect();
+                let right = sum_unneeded(next, &needed_by_left);
+                result = contract(left, right, &needed, &sizes);
+            }
+            None => {
+                result = sum_unneeded(result, &needed);
+                break
+            }
+        }
+    }
+    let order: Vec<usize> = output.iter().map(|label| result.labels.iter().position(|l| l == label).unwrap()).collect();
+    Ok(Permute::forward(result.tensor, &order))
+}
+
+/// Splits an equation into the labels of every operand and of the output.
+fn parse(equation: &str) -> Result<(Vec<Vec<char>>, Vec<char>)> {
+    let equation: String = equation.chars().filter(|c| !c.is_whitespace()).collect();
+    if let Some(c) = equation.chars().find(|&c| !(c.is_ascii_alphabetic() || ",->".contains(c))) {
+        return Err(RsgradError::invalid("einsum", &format!("unexpected character {} in {}", c, equation)))
+    }
+    let (lhs, rhs) = match equation.split_once("->") {
+        Some((lhs, rhs)) => (lhs, Some(rhs)),
+        None => (equation.as_str(), None)
+    };
+    let inputs: Vec<Vec<char>> = lhs.split(',').map(|labels| labels.chars().collect()).collect();
+    let output: Vec<char> = match rhs {
+        Some(rhs) => rhs.chars().collect(),
+        None => {
+            let mut once: Vec<char> = inputs.iter().flatten().copied()
+                .filter(|label| inputs.iter().flatten().filter(|other| *other == label).count() == 1)
+                .collect();
+            once.sort();
+            once
+        }
+    };
+    let arrows = inputs.iter().flatten().chain(output.iter()).any(|&c| c == '-' || c == '>' || c == ',');
+    if arrows {
+        return Err(RsgradError::invalid("einsum", &format!("misplaced separator in {}", equation)))
+    }
+    if let Some(&label) = output.iter().find(|&label| output.iter().filter(|other| *other == label).count() > 1) {
+        return Err(RsgradError::invalid("einsum", &format!("output label {} repeats", label)))
+    }
+    Ok((inputs, output))
+}
+
+/// Takes the diagonal for every label an operand repeats, by masking with an identity and
+/// summing over the second occurrence.
+fn diagonal<T: Float>(mut operand: Operand<T>, sizes: &HashMap<char, u32>) -> Operand<T> {
+    while let Some((first, second)) = repeated(&operand.labels) {
+        let size = sizes[&operand.labels[first]];
+        let mut shape = vec![1; operand.labels.len()];
+        shape[first] = size;
+        shape[second] = size;
+        let eye = Rc::new(RefCell::new(Tensor::eye(size).reshape(&shape)));
+        let masked = Rc::new(RefCell::new(Mult::forward(operand.tensor, eye)));
+        operand.tensor = Rc::new(RefCell::new(SumDim::forward(masked, &[second], false)));
+        operand.labels.remove(second);
+    }
+    operand
+}
+
+fn repeated(labels: &[char]) -> Option<(usize, usize)> {
+    labels.iter().enumerate().find_map(|(first, label)| {
+        labels[first+1..].iter().position(|other| other == label).map(|offset| (first, first + 1 + offset))
+    })
+}
+
+/// Sums over the labels of `operand` that are not `needed`.
+fn sum_unneeded<T: Float>(operand: Operand<T>, needed: &HashSet<char>) -> Operand<T> {
+    let dims: Vec<usize> = (0..operand.labels.len()).filter(|&dim| !needed.contains(&operand.labels[dim])).collect();
+    if dims.is_empty() {
+        return operand
+    }
+    let labels = operand.labels.iter().filter(|label| needed.contains(label)).copied().collect();
+    Operand {tensor: Rc::new(RefCell::new(SumDim::forward(operand.tensor, &dims, false))), labels}
+}
+
+/// Lays `operand` out as a 3-d tensor whose dimensions group the labels in `groups`.
+fn group<T: Float>(operand: &Operand<T>, groups: [&[char]; 3], sizes: &HashMap<char, u32>) -> Rc<RefCell<Tensor<T>>> {
+    let order: Vec<usize> = groups.iter().flat_map(|group| group.iter()).map(|label| operand.labels.iter().position(|l| l == label).unwrap()).collect();
+    let shape: Vec<u32> = groups.iter().map(|group| group.iter().map(|label| sizes[label]).product()).collect();
+    let permuted = Rc::new(RefCell::new(Permute::forward(operand.tensor.clone(), &order)));
+    Rc::new(RefCell::new(Reshape::forward(permuted, &shape)))
+}
+
+/// Multiplies two operands, summing over the labels they share unless these are still
+/// `needed`, in which case they are kept as batch dimensions. The result is labelled batch
+/// labels first, then the remaining labels of `left` and of `right`.
+fn contract<T: Float>(left: Operand<T>, right: Operand<T>, needed: &HashSet<char>, sizes: &HashMap<char, u32>) -> Operand<T> {
+    let shared: Vec<char> = left.labels.iter().filter(|label| right.labels.contains(label)).copied().collect();
+    let batch: Vec<char> = shared.iter().filter(|label| needed.contains(label)).copied().collect();
+    let summed: Vec<char> = shared.iter().filter(|label| !needed.contains(label)).copied().collect();
+    let left_only: Vec<char> = left.labels.iter().filter(|label| !shared.contains(label)).copied().collect();
+    let right_only: Vec<char> = right.labels.iter().filter(|label| !shared.contains(label)).copied().collect();
+
+    let a = group(&left, [&batch, &left_only, &summed], sizes);
+    let b = group(&right, [&batch, &summed, &right_only], sizes);
+    let labels: Vec<char> = batch.iter().chain(left_only.iter()).chain(right_only.iter()).copied().collect();
+    let shape: Vec<u32> = labels.iter().map(|label| sizes[label]).collect();
+    // [batch, left, summed] times [batch, summed, right], a broadcast product if nothing is summed
+    let product = match summed.is_empty() {
+        true => Rc::new(RefCell::new(Mult::forward(a, b))),
+        false => Rc::new(RefCell::new(MatMul::forward(a, b)))
+    };
+    Operand {tensor: Rc::new(RefCell::new(Reshape::forward(product, &shape))), labels}
+}
@@ -0,0 +1,117 @@
+use half::{bf16, f16};
+use std::fmt;
+
+/// The element types a tensor can hold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DType {
+    F32,
+    F64,
+    F16,
+    BF16,
+    I32,
+    I64,
+    U8,
+    Bool
+}
+
+impl DType {
+    /// Whether values of this type are floating point and can take part in autograd.
+    pub fn is_float(&self) -> bool {
+        matches!(self, DType::F32 | DType::F64 | DType::F16 | DType::BF16)
+    }
+}
+
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DType::F32 => "f32",
+            DType::F64 => "f64",
+            DType::F16 => "f16",
+            DType::BF16 => "bf16",
+            DType::I32 => "i32",
+            DType::I64 => "i64",
+            DType::U8 => "u8",
+            DType::Bool => "bool"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A value that can be stored in a tensor. Conversions go through `f64`, which holds every
+/// supported type exactly apart from integers beyond 2^53.
+pub trait Element: Copy + PartialEq + PartialOrd + fmt::Debug + 'static {
+    const DTYPE: DType;
+
+    fn into_f64(self) -> f64;
+
+    /// Converts like an `as` cast: truncating towards zero and saturating for integers,
+    /// nonzero is `true` for bool.
+    fn from_f64(value: f64) -> Self;
+}
+
+/// Element types that autograd ops run on. They are `Send` and `Sync` so that kernels can
+/// split work across threads.
+pub trait Float: Element + num_traits::Float + num_traits::NumAssign + Send + Sync {}
+
+macro_rules! primitive_element {
+    ($type:ty, $dtype:ident) => {
+        impl Element for $type {
+            const DTYPE: DType = DType::$dtype;
+
+            fn into_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn from_f64(value: f64) -> Self {
+                value as $type
+            }
+        }
+    };
+}
+
+primitive_element!(f32, F32);
+primitive_element!(f64, F64);
+primitive_element!(i32, I32);
+primitive_element!(i64, I64);
+primitive_element!(u8, U8);
+
+impl Element for f16 {
+    const DTYPE: DType = DType::F16;
+
+    fn into_f64(self) -> f64 {
+        self.to_f64()
+    }
+
+    fn from_f64(value: f64) -> Self {
+        f16::from_f64(value)
+    }
+}
+
+impl Element for bf16 {
+    const DTYPE: DType = DType::BF16;
+
+    fn into_f64(self) -> f64 {
+        self.to_f64()
+    }
+
+    fn from_f64(value: f64) -> Self {
+        bf16::from_f64(value)
+    }
+}
+
+impl Element for bool {
+    const DTYPE: DType = DType::Bool;
+
+    fn into_f64(self) -> f64 {
+        if self {1.0} else {0.0}
+    }
+
+    fn from_f64(value: f64) -> Self {
+        value != 0.0
+    }
+}
+
+impl Float for f32 {}
+impl Float for f64 {}
+impl Float for f16 {}
+impl Float for bf16 {}
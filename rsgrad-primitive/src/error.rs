@@ -0,0 +1,63 @@
+use std::fmt;
+
+/// Everything the fallible `try_` ops and constructors can report instead of panicking.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RsgradError {
+    /// Two shapes that have to agree, or be broadcastable, do not.
+    ShapeMismatch {op: String, lhs: Vec<u32>, rhs: Vec<u32>},
+    /// A tensor has the wrong number of dimensions for the op.
+    RankMismatch {op: String, expected: usize, got: usize},
+    /// An element index, a dimension or a range end past the size it addresses.
+    IndexOutOfRange {index: usize, size: usize},
+    /// A grad was asked for that backward never produced.
+    MissingGradient,
+    /// Values of one element type were given where another was required.
+    DtypeMismatch {expected: String, got: String},
+    /// An argument the op cannot make sense of, such as a malformed einsum equation.
+    InvalidArgument {op: String, reason: String}
+}
+
+pub type Result<T> = std::result::Result<T, RsgradError>;
+
+impl fmt::Display for RsgradError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RsgradError::ShapeMismatch {op, lhs, rhs} => write!(f, "{}: shapes {:?} and {:?} do not match", op, lhs, rhs),
+            RsgradError::RankMismatch {op, expected, got} => write!(f, "{}: expected {} dimensions, got {}", op, expected, got),
+            RsgradError::IndexOutOfRange {index, size} => write!(f, "index {} out of range for size {}", index, size),
+            RsgradError::MissingGradient => write!(f, "no grad has been computed for this tensor"),
+            RsgradError::DtypeMismatch {expected, got} => write!(f, "expected {} values, got {}", expected, got),
+            RsgradError::InvalidArgument {op, reason} => write!(f, "{}: {}", op, reason)
+        }
+    }
+}
+
+impl std::error::Error for RsgradError {}
+
+impl RsgradError {
+    pub(crate) fn shape(op: &str, lhs: &[u32], rhs: &[u32]) -> RsgradError {
+        RsgradError::ShapeMismatch {op: op.to_string(), lhs: lhs.to_vec(), rhs: rhs.to_vec()}
+    }
+
+    pub(crate) fn rank(op: &str, expected: usize, got: usize) -> RsgradError {
+        RsgradError::RankMismatch {op: op.to_string(), expected, got}
+    }
+
+    pub(crate) fn index(index: usize, size: usize) -> RsgradError {
+        RsgradError::IndexOutOfRange {index, size}
+    }
+
+    pub(crate) fn invalid(op: &str, reason: &str) -> RsgradError {
+        RsgradError::InvalidArgument {op: op.to_string(), reason: reason.to_string()}
+    }
+}
+
+/// The panicking counterpart of a `try_` call: unwraps the value or panics with the error message.
+pub(crate) fn or_panic<T>(result: Result<T>) -> T {
+    result.unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Fails with [`RsgradError::IndexOutOfRange`] unless `index < size`.
+pub(crate) fn check_index(index: usize, size: usize) -> Result<()> {
+    if index < size {Ok(())} else {Err(RsgradError::index(index, size))}
+}
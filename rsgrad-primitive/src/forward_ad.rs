@@ -0,0 +1,52 @@
+use crate::tensor::Tensor;
+use crate::element::Float;
+use std::rc::Rc;
+use std::cell::RefCell;
+
+/// Evaluates `f` at `primals` while pushing `tangents` forward through every op, returning the
+/// output together with its directional derivative. The tangents are attached to the primals
+/// for the duration of the call only.
+pub fn jvp<T: Float, F>(f: F, primals: &[Rc<RefCell<Tensor<T>>>], tangents: Vec<Tensor<T>>) -> (Tensor<T>, Tensor<T>)
+where F: Fn(&[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+    assert_eq!(primals.len(), tangents.len());
+    for (primal, tangent) in primals.iter().zip(tangents) {
+        assert_eq!(primal.borrow().shape(), tangent.shape());
+        primal.borrow_mut().tangent = Some(Rc::new(RefCell::new(tangent)));
+    }
+    let mut output = f(primals);
+    for primal in primals {
+        primal.borrow_mut().tangent = None;
+    }
+    let tangent = match output.tangent.take() {
+        Some(tangent) => tangent.borrow().contiguous(),
+        None => Tensor::constant_fill(T::zero(), &output.shape())
+    };
+    (output, tangent)
+}
+
+/// Builds the full Jacobian of `f` at `x` column by column with forward mode, one pass per
+/// input element. The result has shape `output shape ++ input shape`.
+pub fn jacobian<T: Float, F>(f: F, x: Rc<RefCell<Tensor<T>>>) -> Tensor<T>
+where F: Fn(Rc<RefCell<Tensor<T>>>) -> Tensor<T> {
+    let in_shape = x.borrow().shape();
+    let in_size = x.borrow().numel();
+    let mut columns: Vec<Tensor<T>> = Vec::with_capacity(in_size);
+    // an empty input has no columns to take the output shape from
+    let mut out_shape: Vec<u32> = if in_size == 0 {f(x.clone()).shape()} else {Vec::new()};
+    for idx in 0..in_size {
+        let mut seed: Vec<T> = vec![T::zero(); in_size];
+        seed[idx] = T::one();
+        let (output, tangent) = jvp(|primals| f(primals[0].clone()), std::slice::from_ref(&x), vec![Tensor::new(seed, &in_shape)]);
+        out_shape = output.shape();
+        columns.push(tangent);
+    }
+    let out_size: usize = out_shape.iter().map(|&size| size as usize).product();
+    let mut buffer: Vec<T> = vec![T::zero(); out_size*in_size];
+    for (col, column) in columns.iter().enumerate() {
+        for (row, value) in column.values().iter().enumerate() {
+            buffer[row*in_size + col] = *value;
+        }
+    }
+    out_shape.extend(in_shape);
+    Tensor::new(buffer, &out_shape)
+}
@@ -0,0 +1,90 @@
+use crate::element::Float;
+
+/// Rows of the output handled together, and the unit of work handed to a thread.
+const ROW_BLOCK: usize = 64;
+/// Length of the slice of the inner dimension whose rows of `b` are reused across a row block.
+const DEPTH_BLOCK: usize = 128;
+/// Width of the output columns a row block works on at a time, so that a `DEPTH_BLOCK` by
+/// `COLUMN_BLOCK` panel of `b` stays in cache.
+const COLUMN_BLOCK: usize = 256;
+/// Rows updated together by the innermost loop, sharing every load from `b`.
+const MICRO_ROWS: usize = 4;
+/// Products with fewer multiply-adds than this stay on the calling thread.
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 1 << 18;
+
+/// `out += a b` for row-major `a` of `n` by `k`, `b` of `k` by `m` and `out` of `n` by `m`.
+///
+/// This is the entry point every dense product goes through. With the `blas` feature, `f32`
+/// and `f64` products are handed to the system BLAS, everything else runs [`native_gemm`].
+pub fn gemm<T: Float>(a: &[T], b: &[T], out: &mut [T], n: usize, k: usize, m: usize) {
+    assert!(a.len() == n*k && b.len() == k*m && out.len() == n*m, "gemm buffers do not match {}x{} times {}x{}", n, k, k, m);
+    #[cfg(feature = "blas")]
+    if crate::blas::gemm(a, b, out, n, k, m) {
+        return
+    }
+    native_gemm(a, b, out, n, k, m);
+}
+
+/// [`gemm`] without the BLAS backend.
+///
+/// The product is tiled so that panels of `b` are reused from cache across a block of output
+/// rows, and the innermost loop runs along contiguous rows of `b` and `out`, which the
+/// compiler vectorizes. With the `parallel` feature, blocks of output rows of large products
+/// are spread over the rayon thread pool.
+pub fn native_gemm<T: Float>(a: &[T], b: &[T], out: &mut [T], n: usize, k: usize, m: usize) {
+    assert!(a.len() == n*k && b.len() == k*m && out.len() == n*m, "gemm buffers do not match {}x{} times {}x{}", n, k, k, m);
+    if n == 0 || m == 0 {
+        return
+    }
+    let block = |(index, rows): (usize, &mut [T])| {
+        let first = index*ROW_BLOCK;
+        row_block(&a[first*k..(first + rows.len()/m)*k], b, rows, k, m);
+    };
+    #[cfg(feature = "parallel")]
+    if n*k*m >= PARALLEL_THRESHOLD {
+        use rayon::prelude::*;
+        out.par_chunks_mut(ROW_BLOCK*m).enumerate().for_each(block);
+        return
+    }
+    out.chunks_mut(ROW_BLOCK*m).enumerate().for_each(block);
+}
+
+/// Accumulates the rows of `a` times `b` into `out`, which holds as many rows as `a`.
+fn row_block<T: Float>(a: &[T], b: &[T], out: &mut [T], k: usize, m: usize) {
+    let rows = out.len()/m;
+    for columns_start in (0..m).step_by(COLUMN_BLOCK) {
+        let columns = columns_start..(columns_start + COLUMN_BLOCK).min(m);
+        for depth_start in (0..k).step_by(DEPTH_BLOCK) {
+            let depth = depth_start..(depth_start + DEPTH_BLOCK).min(k);
+            let mut row = 0;
+            while row + MICRO_ROWS <= rows {
+                let (r0, rest) = out[row*m..(row + MICRO_ROWS)*m].split_at_mut(m);
+                let (r1, rest) = rest.split_at_mut(m);
+                let (r2, r3) = rest.split_at_mut(m);
+                for p in depth.clone() {
+                    let scales = [a[row*k + p], a[(row + 1)*k + p], a[(row + 2)*k + p], a[(row + 3)*k + p]];
+                    let b_row = &b[p*m + columns.start..p*m + columns.end];
+                    let targets = r0[columns.clone()].iter_mut().zip(r1[columns.clone()].iter_mut())
+                        .zip(r2[columns.clone()].iter_mut().zip(r3[columns.clone()].iter_mut()));
+                    for (&value, ((t0, t1), (t2, t3))) in b_row.iter().zip(targets) {
+                        *t0 += scales[0]*value;
+                        *t1 += scales[1]*value;
+                        *t2 += scales[2]*value;
+                        *t3 += scales[3]*value;
+                    }
+                }
+                row += MICRO_ROWS;
+            }
+            for row in row..rows {
+                let target = &mut out[row*m + columns.start..row*m + columns.end];
+                for p in depth.clone() {
+                    let scale = a[row*k + p];
+                    for (target, &value) in target.iter_mut().zip(b[p*m + columns.start..p*m + columns.end].iter()) {
+                        *target += scale*value;
+                    }
+                }
+            }
+        }
+    }
+}
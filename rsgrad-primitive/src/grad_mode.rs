@@ -0,0 +1,40 @@
+use std::cell::Cell;
+
+thread_local! {
+    static GRAD_ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// Whether ops currently record their inputs for backpropagation.
+pub fn is_grad_enabled() -> bool {
+    GRAD_ENABLED.with(|enabled| enabled.get())
+}
+
+/// Switches graph construction on or off for the current thread until it is dropped, at which
+/// point the previous mode is restored. Guards nest.
+pub struct GradModeGuard {
+    previous: bool
+}
+
+impl GradModeGuard {
+    pub fn new(enabled: bool) -> GradModeGuard {
+        let previous = GRAD_ENABLED.with(|current| current.replace(enabled));
+        GradModeGuard {previous}
+    }
+}
+
+impl Drop for GradModeGuard {
+    fn drop(&mut self) {
+        GRAD_ENABLED.with(|current| current.set(self.previous));
+    }
+}
+
+/// Disables graph construction for as long as the returned guard lives.
+pub fn no_grad_guard() -> GradModeGuard {
+    GradModeGuard::new(false)
+}
+
+/// Runs `f` without recording any ops, e.g. for validation loops and parameter updates.
+pub fn no_grad<R>(f: impl FnOnce() -> R) -> R {
+    let _guard = no_grad_guard();
+    f()
+}
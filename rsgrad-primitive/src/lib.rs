@@ -1,5 +1,18 @@
+pub mod element;
+pub mod error;
 pub mod tensor;
+pub mod storage;
+pub mod nested;
+pub mod random;
+pub mod display;
+pub mod gemm;
+#[cfg(feature = "blas")]
+pub mod blas;
 pub mod ops;
+pub mod einsum;
+pub mod forward_ad;
+pub mod grad_mode;
+pub mod var;
 use tensor::Tensor;
 use std::rc::Rc;
 use std::cell::RefCell;
@@ -11,6 +24,7 @@ pub fn add(left: usize, right: usize) -> usize {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ops::Function;
     //mod tensor;
     //use tensor::Tensor;
 
@@ -22,7 +36,7 @@ mod tests {
 
     #[test]
     fn tensor_initialisation() {
-        let data: Vec<f32> = vec![1.0; 10];
+        let data: Vec<f32> = vec![1.0; 24];
         let shape: &[u32] = &[3, 2, 4];
         let a = Tensor::new(data, shape);
         assert_eq!(a.stride, vec![8, 4, 1]);
@@ -39,7 +53,7 @@ mod tests {
     #[test]
     fn rand_tensor() {
         let shape: &[u32] = &[3, 2, 4];
-        let a = Tensor::rand(shape);
+        let a: Tensor = Tensor::rand(shape);
         assert_eq!(a.stride, vec![8, 4, 1]);
     }
 
@@ -54,6 +68,97 @@ mod tests {
         assert_eq!(a.at(&[1, 1, 1]), &2.0);
     }
 
+    #[test]
+    fn scalar_and_empty_tensor_test() {
+        let s = Tensor::scalar(3.0);
+        assert_eq!(s.shape(), Vec::<u32>::new());
+        assert_eq!(s.numel(), 1);
+        assert_eq!(*s.at_im(&[]), 3.0);
+        let e = Tensor::constant_fill(1.0, &[0, 3]);
+        assert_eq!(e.shape(), vec![0, 3]);
+        assert_eq!(e.stride, vec![3, 1]);
+        assert_eq!(e.numel(), 0);
+        let sizes = Tensor::new(vec![1.0, 2.0], &[1, 2, 1]);
+        assert_eq!(sizes.shape(), vec![1, 2, 1]);
+        let a = Rc::new(RefCell::new(e));
+        let mut sum = ops::Sum::forward(a.clone());
+        assert_eq!(sum.item(), 0.0);
+        sum.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().shape(), vec![0, 3]);
+    }
+
+    #[test]
+    fn strided_tensor_test() {
+        // a column of a 3x2 matrix, stored with an offset and a non unit stride
+        let mut column = Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[3, 2]);
+        column.shape = vec![3];
+        column.stride = vec![2];
+        column.offset = 1;
+        assert!(!column.is_contiguous());
+        assert_eq!(column.values().to_vec(), vec![2.0, 4.0, 6.0]);
+        assert_eq!(*column.at_im(&[2]), 6.0);
+        let a = Rc::new(RefCell::new(column));
+        let b = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3])));
+        let result = ops::Add::forward(a, b);
+        assert!(result.is_contiguous());
+        assert_eq!(result.buffer, vec![3.0, 5.0, 7.0]);
+    }
+
+    #[test]
+    fn broadcast_shape_test() {
+        assert_eq!(tensor::broadcast_shape(&[4, 3], &[1, 3]), vec![4, 3]);
+        assert_eq!(tensor::broadcast_shape(&[3, 1], &[2, 1, 4]), vec![2, 3, 4]);
+        assert_eq!(tensor::broadcast_shape(&[], &[2, 2]), vec![2, 2]);
+        assert_eq!(tensor::broadcast_shape(&[0, 1], &[3]), vec![0, 3]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn broadcast_incompatible_test() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2, 3])));
+        let b = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2])));
+        ops::Add::forward(a, b);
+    }
+
+    #[test]
+    fn broadcast_bias_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[3, 2])));
+        let bias = Rc::new(RefCell::new(Tensor::new(vec![10.0, 20.0], &[1, 2])));
+        let mut result = ops::Add::forward(x.clone(), bias.clone());
+        assert_eq!(result.shape(), vec![3, 2]);
+        assert_eq!(result.buffer, vec![11.0, 22.0, 13.0, 24.0, 15.0, 26.0]);
+        result.backward(Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[3, 2]))));
+        assert_eq!(bias.borrow().grad.as_ref().unwrap().borrow().shape(), vec![1, 2]);
+        assert_eq!(bias.borrow().grad.as_ref().unwrap().borrow().buffer, vec![9.0, 12.0]);
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn broadcast_outer_product_test() {
+        let a = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0], &[3, 1])));
+        let b = Rc::new(RefCell::new(Tensor::new(vec![1.0, -1.0], &[2])));
+        let mut result = ops::Mult::forward(a.clone(), b.clone());
+        assert_eq!(result.shape(), vec![3, 2]);
+        assert_eq!(result.buffer, vec![1.0, -1.0, 2.0, -2.0, 3.0, -3.0]);
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3, 2]))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![0.0; 3]);
+        assert_eq!(b.borrow().grad.as_ref().unwrap().borrow().buffer, vec![6.0, 6.0]);
+    }
+
+    #[test]
+    fn broadcast_scalar_division_test() {
+        let a = Rc::new(RefCell::new(Tensor::new(vec![2.0, 4.0], &[2])));
+        let s = Rc::new(RefCell::new(Tensor::scalar(2.0)));
+        let quotient = Rc::new(RefCell::new(ops::Div::forward(a.clone(), s.clone())));
+        let mut result = ops::Sub::forward(quotient, s.clone());
+        assert_eq!(result.buffer, vec![-1.0, 0.0]);
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2]))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![0.5, 0.5]);
+        // d/ds sum(a/s - s) = -sum(a)/s^2 - 2
+        assert_eq!(s.borrow().grad.as_ref().unwrap().borrow().shape(), Vec::<u32>::new());
+        assert_eq!(s.borrow().grad.as_ref().unwrap().borrow().item(), -3.5);
+    }
+
     #[test]
     fn addition_test() {
         let shape: &[u32] = &[3, 2, 4];
@@ -82,7 +187,7 @@ mod tests {
         x.push(a.clone());
         x.push(b.clone());
         let grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, shape)));
-        let mut result = ops::Add::vjp(grad, &x);
+        let mut result = ops::Add.vjp(grad, &x);
         assert_eq!(*result[0].at(&[1, 1, 1]), 1.0);
     }
 
@@ -96,7 +201,7 @@ mod tests {
         x.push(a.clone());
         x.push(b.clone());
         let grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, shape)));
-        let mut result = ops::Mult::vjp(grad, &x);
+        let mut result = ops::Mult.vjp(grad, &x);
         assert_eq!(*result[0].at(&[1, 1, 1]), 3.0);
     }
 
@@ -139,6 +244,244 @@ mod tests {
         assert_eq!(*c_local.borrow_mut().grad.as_ref().unwrap().borrow_mut().at(&[1,1,1]), 3.0+(2.0_f32).ln());
     }
 
+    #[test]
+    fn backward_test_reused_input() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(3.0, &[2, 2])));
+        let mut result = ops::Mult::forward(a.clone(), a.clone());
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2, 2]))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![6.0; 4]);
+    }
+
+    #[test]
+    fn backward_test_diamond() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[3])));
+        let k = Rc::new(RefCell::new(Tensor::constant_fill(3.0, &[3])));
+        let a_log = Rc::new(RefCell::new(ops::Log::forward(a.clone())));
+        let a_scaled = Rc::new(RefCell::new(ops::Mult::forward(a.clone(), k.clone())));
+        let mut result = ops::Add::forward(a_log, a_scaled);
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![3.5; 3]);
+        assert_eq!(k.borrow().grad.as_ref().unwrap().borrow().buffer, vec![2.0; 3]);
+    }
+
+    #[test]
+    fn backward_test_shared_weight() {
+        let x = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 2])));
+        let w = Rc::new(RefCell::new(Tensor::constant_fill(0.5, &[2, 2])));
+        let h1 = Rc::new(RefCell::new(ops::MatMul::forward(x, w.clone())));
+        let mut h2 = ops::MatMul::forward(h1.clone(), w.clone());
+        h2.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 2]))));
+        assert_eq!(h1.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0; 2]);
+        assert_eq!(w.borrow().grad.as_ref().unwrap().borrow().buffer, vec![2.0; 4]);
+    }
+
+    #[test]
+    fn backward_test_accumulates_leaves_only() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[3])));
+        let b = Rc::new(RefCell::new(Tensor::constant_fill(3.0, &[3])));
+        let sum = Rc::new(RefCell::new(ops::Add::forward(a.clone(), b.clone())));
+        let mut result = ops::Mult::forward(sum.clone(), a.clone());
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))));
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))));
+        assert_eq!(sum.borrow().grad.as_ref().unwrap().borrow().buffer, vec![2.0; 3]);
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![14.0; 3]);
+        assert_eq!(b.borrow().grad.as_ref().unwrap().borrow().buffer, vec![4.0; 3]);
+    }
+
+    #[test]
+    fn backward_test_deep_chain() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2])));
+        let one = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2])));
+        let mut current = a.clone();
+        for _ in 0..100_000 {
+            current = Rc::new(RefCell::new(ops::Mult::forward(current, one.clone())));
+        }
+        current.borrow_mut().backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2]))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0; 2]);
+        assert_eq!(one.borrow().grad.as_ref().unwrap().borrow().buffer, vec![100_000.0; 2]);
+    }
+
+    #[test]
+    fn second_derivative_test() {
+        let x = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[3])));
+        let x_squared = Rc::new(RefCell::new(ops::Mult::forward(x.clone(), x.clone())));
+        let mut x_cubed = ops::Mult::forward(x_squared, x.clone());
+        x_cubed.backward_with(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))), true);
+        let first = x.borrow_mut().grad.take().unwrap();
+        assert_eq!(first.borrow().buffer, vec![12.0; 3]);
+        first.borrow_mut().backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![12.0; 3]);
+    }
+
+    #[test]
+    fn second_derivative_test_with_log() {
+        let x = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[3])));
+        let mut x_log = ops::Log::forward(x.clone());
+        x_log.backward_with(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))), true);
+        let first = x.borrow_mut().grad.take().unwrap();
+        assert_eq!(first.borrow().buffer, vec![0.5; 3]);
+        first.borrow_mut().backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![-0.25; 3]);
+    }
+
+    #[test]
+    fn hessian_vector_product_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0], &[3])));
+        let v = Rc::new(RefCell::new(Tensor::new(vec![1.0, -1.0, 0.5], &[3])));
+        let mut norm = ops::L2norm::forward(x.clone());
+        norm.backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), true);
+        let first = x.borrow_mut().grad.take().unwrap();
+        assert_eq!(first.borrow().buffer, vec![2.0, 4.0, 6.0]);
+        let first_v = Rc::new(RefCell::new(ops::Mult::forward(first, v)));
+        let mut directional = ops::Sum::forward(first_v);
+        directional.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![2.0, -2.0, 1.0]);
+    }
+
+    #[test]
+    fn gradient_penalty_matmul_test() {
+        // d/dw ||x w||^2 = 2 x^T x w, its squared norm again depends on w
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0], &[1, 2])));
+        let w = Rc::new(RefCell::new(Tensor::new(vec![1.0, 1.0], &[2, 1])));
+        let y = Rc::new(RefCell::new(ops::MatMul::forward(x.clone(), w.clone())));
+        let mut loss = ops::L2norm::forward(y);
+        loss.backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), true);
+        let grad_w = w.borrow_mut().grad.take().unwrap();
+        assert_eq!(grad_w.borrow().buffer, vec![6.0, 12.0]);
+        let mut penalty = ops::L2norm::forward(grad_w);
+        penalty.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        // penalty = 4 (x w)^2 |x|^2, d/dw = 8 |x|^2 (x w) x^T = 8 * 5 * 3 * [1, 2]
+        assert_eq!(w.borrow().grad.as_ref().unwrap().borrow().buffer, vec![120.0, 240.0]);
+    }
+
+    #[test]
+    fn jvp_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0], &[3])));
+        let (output, tangent) = forward_ad::jvp(|p| ops::Mult::forward(p[0].clone(), p[0].clone()), std::slice::from_ref(&x), vec![Tensor::new(vec![1.0, 0.5, -1.0], &[3])]);
+        assert_eq!(output.buffer, vec![1.0, 4.0, 9.0]);
+        assert_eq!(tangent.buffer, vec![2.0, 2.0, -6.0]);
+        assert!(x.borrow().tangent.is_none());
+    }
+
+    #[test]
+    fn jvp_matches_vjp_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, -2.0, 0.5, 3.0], &[2, 2])));
+        let w = Rc::new(RefCell::new(Tensor::new(vec![0.5, -1.0, 2.0, 1.5], &[2, 2])));
+        let f = |p: &[Rc<RefCell<Tensor>>]| {
+            let h = Rc::new(RefCell::new(ops::MatMul::forward(p[0].clone(), p[1].clone())));
+            let r = Rc::new(RefCell::new(ops::Relu::forward(h)));
+            let shifted = Rc::new(RefCell::new(ops::Add::forward(r, Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[2, 2]))))));
+            let logged = Rc::new(RefCell::new(ops::Log::forward(shifted)));
+            ops::L2norm::forward(logged)
+        };
+        let v = Tensor::new(vec![1.0, 2.0, -1.0, 0.5], &[2, 2]);
+        let (mut output, tangent) = forward_ad::jvp(f, &[x.clone(), w.clone()], vec![Tensor::constant_fill(0.0, &[2, 2]), v.clone()]);
+        output.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        let expected: f32 = w.borrow().grad.as_ref().unwrap().borrow().buffer.iter().zip(v.buffer.iter()).map(|(g, v)| g*v).sum();
+        assert!((tangent.buffer[0] - expected).abs() < 1e-5);
+    }
+
+    #[test]
+    fn jacobian_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 4.0], &[3])));
+        let jac = forward_ad::jacobian(|x| {
+            let y = Rc::new(RefCell::new(ops::Mult::forward(x.clone(), x.clone())));
+            ops::Div::forward(y, Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[3]))))
+        }, x);
+        assert_eq!(jac.shape(), vec![3, 3]);
+        assert_eq!(jac.buffer, vec![1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 4.0]);
+
+        let empty = Rc::new(RefCell::new(Tensor::<f32>::zeros(&[2, 0])));
+        let jac = forward_ad::jacobian(|x| ops::SumDim::forward(x, &[1], false), empty);
+        assert_eq!(jac.shape(), vec![2, 2, 0]);
+        assert_eq!(jac.numel(), 0);
+    }
+
+    #[test]
+    fn no_grad_test() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[3])));
+        let b = Rc::new(RefCell::new(Tensor::constant_fill(3.0, &[3])));
+        let result = grad_mode::no_grad(|| ops::Mult::forward(a.clone(), b.clone()));
+        assert_eq!(result.buffer, vec![6.0; 3]);
+        assert!(result.op.is_none() && result.children.is_empty() && !result.requires_grad);
+        {
+            let _guard = grad_mode::no_grad_guard();
+            let _nested = grad_mode::no_grad_guard();
+            assert!(ops::Add::forward(a.clone(), b.clone()).children.is_empty());
+        }
+        assert!(grad_mode::is_grad_enabled());
+        let result = ops::Add::forward(a, b);
+        assert_eq!(result.children.len(), 2);
+        assert!(result.requires_grad);
+    }
+
+    #[test]
+    fn requires_grad_test() {
+        let x = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[1, 2]).with_requires_grad(false)));
+        let t = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 1]).with_requires_grad(false)));
+        let w = Rc::new(RefCell::new(Tensor::constant_fill(0.5, &[2, 1])));
+        let y = Rc::new(RefCell::new(ops::MatMul::forward(x.clone(), w.clone())));
+        let constant = ops::Sub::forward(x.clone(), x.clone());
+        assert!(constant.op.is_none() && !constant.requires_grad);
+        let mut loss = ops::L2norm::forward(Rc::new(RefCell::new(ops::Sub::forward(y, t.clone()))));
+        loss.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(w.borrow().grad.as_ref().unwrap().borrow().buffer, vec![4.0, 4.0]);
+        assert!(x.borrow().grad.is_none());
+        assert!(t.borrow().grad.is_none());
+    }
+
+    #[test]
+    fn backward_does_not_record_test() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[3])));
+        let mut result = ops::Log::forward(a.clone());
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))));
+        let grad = a.borrow().grad.clone().unwrap();
+        assert!(grad.borrow().op.is_none() && grad.borrow().children.is_empty());
+    }
+
+    struct Cube;
+
+    impl Function for Cube {
+        fn name(&self) -> &str {
+            "Cube"
+        }
+
+        fn forward(&self, x: &[Rc<RefCell<Tensor>>]) -> Tensor {
+            let a = x[0].borrow();
+            Tensor::new(a.buffer.iter().map(|v| v*v*v).collect(), &a.shape())
+        }
+
+        fn vjp(&self, grad: Rc<RefCell<Tensor>>, x: &[Rc<RefCell<Tensor>>]) -> Vec<Tensor> {
+            let squared = Rc::new(RefCell::new(ops::Mult::forward(x[0].clone(), x[0].clone())));
+            let threes = Rc::new(RefCell::new(Tensor::constant_fill(3.0, &x[0].borrow().shape())));
+            let derivative = Rc::new(RefCell::new(ops::Mult::forward(squared, threes)));
+            vec![ops::Mult::forward(grad, derivative)]
+        }
+    }
+
+    #[test]
+    fn custom_function_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0], &[2])));
+        let cubed = Rc::new(RefCell::new(ops::apply(Rc::new(Cube), vec![x.clone()])));
+        assert_eq!(cubed.borrow().op.as_ref().unwrap().name(), "Cube");
+        let mut result = ops::Log::forward(cubed);
+        result.backward_with(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2]))), true);
+        // d/dx log(x^3) = 3/x, d2/dx2 = -3/x^2
+        let first = x.borrow_mut().grad.take().unwrap();
+        assert_eq!(first.borrow().buffer, vec![3.0, 1.5]);
+        first.borrow_mut().backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![-3.0, -0.75]);
+    }
+
+    #[test]
+    fn custom_function_default_jvp_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, -1.0], &[3])));
+        let (output, tangent) = forward_ad::jvp(|p| ops::apply(Rc::new(Cube), p.to_vec()), std::slice::from_ref(&x), vec![Tensor::new(vec![1.0, 0.5, 2.0], &[3])]);
+        assert_eq!(output.buffer, vec![1.0, 8.0, -1.0]);
+        assert_eq!(tangent.buffer, vec![3.0, 6.0, 6.0]);
+        assert!(x.borrow().grad.is_none());
+    }
+
     #[test]
     fn matmul_test() {
         let mut a = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[6, 2])));
@@ -157,10 +500,1018 @@ mod tests {
     fn L2norm_test() {
         let mut a = Rc::new(RefCell::new(Tensor::constant_fill(2.0, &[6])));
         let mut result = ops::L2norm::forward(a.clone());
-        let mut init_grad = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1])));
+        let mut init_grad = Rc::new(RefCell::new(Tensor::scalar(1.0)));
         result.backward(init_grad);
-        assert_eq!(*result.at_im(&[0]), 24.0);
+        assert_eq!(*result.at_im(&[]), 24.0);
         assert_eq!(*a.borrow_mut().grad.as_ref().unwrap().borrow_mut().at(&[1]), 4.0);
     }
 
+    #[test]
+    fn reshape_view_test() {
+        let base = Tensor::new((0..6).map(|v| v as f32).collect(), &[2, 3]);
+        let view = base.reshape(&[3, 2]);
+        assert!(storage::Storage::ptr_eq(&base.buffer, &view.buffer));
+        assert_eq!(view.shape, vec![3, 2]);
+        assert_eq!(*view.at_im(&[2, 0]), 4.0);
+        let copied = base.transpose().reshape(&[6]);
+        assert!(!storage::Storage::ptr_eq(&base.buffer, &copied.buffer));
+        assert_eq!(copied.buffer, vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+    }
+
+    #[test]
+    fn view_copy_on_write_test() {
+        let mut base = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2]);
+        let view = base.transpose();
+        *base.at(&[0, 1]) = 9.0;
+        assert_eq!(base.values().to_vec(), vec![1.0, 9.0, 3.0, 4.0]);
+        assert_eq!(view.values().to_vec(), vec![1.0, 3.0, 2.0, 4.0]);
+    }
+
+    #[test]
+    fn permute_test() {
+        let x = Rc::new(RefCell::new(Tensor::new((0..24).map(|v| v as f32).collect(), &[2, 3, 4])));
+        let permuted = Rc::new(RefCell::new(ops::Permute::forward(x.clone(), &[2, 0, 1])));
+        assert_eq!(permuted.borrow().shape, vec![4, 2, 3]);
+        assert_eq!(permuted.borrow().stride, vec![1, 12, 4]);
+        assert_eq!(*permuted.borrow().at_im(&[3, 1, 2]), 23.0);
+        let weights = Rc::new(RefCell::new(Tensor::new((0..24).map(|v| v as f32).collect(), &[4, 2, 3])));
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(permuted, weights))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        let grad = x.borrow().grad.clone().unwrap();
+        assert_eq!(grad.borrow().shape, vec![2, 3, 4]);
+        // x[i, j, k] was multiplied by weights[k, i, j]
+        assert_eq!(*grad.borrow().at_im(&[1, 2, 3]), 23.0);
+        assert_eq!(*grad.borrow().at_im(&[0, 1, 2]), 13.0);
+    }
+
+    #[test]
+    fn expand_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0], &[3, 1])));
+        let expanded = Rc::new(RefCell::new(ops::Expand::forward(x.clone(), &[2, 3, 4])));
+        assert_eq!(expanded.borrow().stride, vec![0, 1, 0]);
+        assert!(storage::Storage::ptr_eq(&x.borrow().buffer, &expanded.borrow().buffer));
+        assert_eq!(*expanded.borrow().at_im(&[1, 2, 3]), 3.0);
+        let mut result = ops::Sum::forward(expanded);
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(*result.at_im(&[]), 48.0);
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![8.0, 8.0, 8.0]);
+    }
+
+    #[test]
+    fn squeeze_unsqueeze_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0], &[3])));
+        let unsqueezed = Rc::new(RefCell::new(ops::Unsqueeze::forward(x.clone(), 0)));
+        assert_eq!(unsqueezed.borrow().shape, vec![1, 3]);
+        let column = Rc::new(RefCell::new(ops::Unsqueeze::forward(x.clone(), 1)));
+        assert_eq!(column.borrow().shape, vec![3, 1]);
+        let squeezed = Rc::new(RefCell::new(ops::Squeeze::forward(unsqueezed, 0)));
+        assert_eq!(squeezed.borrow().shape, vec![3]);
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(squeezed, column))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        // sum over i, j of x[i] * x[j]
+        assert_eq!(*result.at_im(&[]), 36.0);
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![12.0, 12.0, 12.0]);
+    }
+
+    #[test]
+    fn transposed_matmul_test() {
+        let a = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[3, 2])));
+        let transposed = Rc::new(RefCell::new(ops::Transpose::forward(a.clone())));
+        assert!(!transposed.borrow().is_contiguous());
+        let b = Rc::new(RefCell::new(Tensor::new(vec![1.0, 0.0, 0.0, 1.0, 1.0, 1.0], &[3, 2])));
+        let mut result = ops::MatMul::forward(transposed, b);
+        assert_eq!(result.values().to_vec(), vec![6.0, 8.0, 8.0, 10.0]);
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2, 2]))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 1.0, 1.0, 1.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn reshape_chain_test() {
+        let x = Rc::new(RefCell::new(Tensor::new((0..6).map(|v| v as f32).collect(), &[6])));
+        let reshaped = Rc::new(RefCell::new(ops::Reshape::forward(x.clone(), &[2, 3])));
+        let permuted = Rc::new(RefCell::new(ops::Permute::forward(reshaped, &[1, 0])));
+        let flat = Rc::new(RefCell::new(ops::Reshape::forward(permuted, &[6])));
+        assert_eq!(flat.borrow().buffer, vec![0.0, 3.0, 1.0, 4.0, 2.0, 5.0]);
+        let weights = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[6])));
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(flat, weights))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 3.0, 5.0, 2.0, 4.0, 6.0]);
+    }
+
+    #[test]
+    fn slice_test() {
+        let x = Rc::new(RefCell::new(Tensor::new((0..12).map(|v| v as f32).collect(), &[3, 4])));
+        let sliced = Rc::new(RefCell::new(ops::Slice::forward(x.clone(), 1, 1, 4, 2)));
+        assert_eq!(sliced.borrow().shape, vec![3, 2]);
+        assert!(storage::Storage::ptr_eq(&x.borrow().buffer, &sliced.borrow().buffer));
+        assert_eq!(sliced.borrow().values().to_vec(), vec![1.0, 3.0, 5.0, 7.0, 9.0, 11.0]);
+        let rows = Rc::new(RefCell::new(ops::Slice::forward(sliced, 0, 1, 3, 1)));
+        assert_eq!(rows.borrow().values().to_vec(), vec![5.0, 7.0, 9.0, 11.0]);
+        let mut result = ops::L2norm::forward(rows);
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![0.0, 0.0, 0.0, 0.0, 0.0, 10.0, 0.0, 14.0, 0.0, 18.0, 0.0, 22.0]);
+    }
+
+    #[test]
+    fn slice_jvp_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[4])));
+        let (output, tangent) = forward_ad::jvp(|p| ops::Slice::forward(p[0].clone(), 0, 1, 3, 1), std::slice::from_ref(&x), vec![Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[4])]);
+        assert_eq!(output.values().to_vec(), vec![2.0, 3.0]);
+        assert_eq!(tangent.values().to_vec(), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn index_select_test() {
+        let x = Rc::new(RefCell::new(Tensor::new((0..6).map(|v| v as f32).collect(), &[3, 2])));
+        let mut rows = ops::IndexSelect::forward(x.clone(), 0, &[2, 0, 2]);
+        assert_eq!(rows.shape, vec![3, 2]);
+        assert_eq!(rows.buffer, vec![4.0, 5.0, 0.0, 1.0, 4.0, 5.0]);
+        rows.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3, 2]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 1.0, 0.0, 0.0, 2.0, 2.0]);
+        let columns = ops::IndexSelect::forward(x, 1, &[1]);
+        assert_eq!(columns.buffer, vec![1.0, 3.0, 5.0]);
+    }
+
+    #[test]
+    fn gather_class_label_test() {
+        // negative log likelihood of the labelled class for each row
+        let probs = Rc::new(RefCell::new(Tensor::new(vec![0.5, 0.25, 0.25, 0.1, 0.8, 0.1], &[2, 3])));
+        let log_probs = Rc::new(RefCell::new(ops::Log::forward(probs.clone())));
+        let labels = Tensor::new(vec![0, 1], &[2, 1]);
+        let picked = Rc::new(RefCell::new(ops::Gather::forward(log_probs, 1, &labels)));
+        assert_eq!(picked.borrow().shape, vec![2, 1]);
+        let mut loss = ops::Sum::forward(picked);
+        assert!((loss.item() - (0.5f32.ln() + 0.8f32.ln())).abs() < 1e-6);
+        loss.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(probs.borrow().grad.as_ref().unwrap().borrow().buffer, vec![2.0, 0.0, 0.0, 0.0, 1.25, 0.0]);
+    }
+
+    #[test]
+    fn scatter_add_test() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2, 3])));
+        let src = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2])));
+        let index = Tensor::new(vec![2, 2, 0, 1], &[2, 2]);
+        let result = Rc::new(RefCell::new(ops::ScatterAdd::forward(a.clone(), 1, &index, src.clone())));
+        assert_eq!(result.borrow().buffer, vec![1.0, 1.0, 4.0, 4.0, 5.0, 1.0]);
+        let weights = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3])));
+        let mut total = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(result, weights))));
+        total.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(src.borrow().grad.as_ref().unwrap().borrow().buffer, vec![3.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn masked_select_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, -2.0, 3.0, -4.0, 5.0, 6.0], &[2, 3])));
+        let mask = Tensor::new(vec![true, false, true], &[3]);
+        let selected = Rc::new(RefCell::new(ops::MaskedSelect::forward(x.clone(), &mask)));
+        assert_eq!(selected.borrow().shape, vec![4]);
+        assert_eq!(selected.borrow().buffer, vec![1.0, 3.0, -4.0, 6.0]);
+        let mut result = ops::L2norm::forward(selected);
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![2.0, 0.0, 6.0, -8.0, 0.0, 12.0]);
+    }
+
+    #[test]
+    fn sum_dim_test() {
+        let x = Rc::new(RefCell::new(Tensor::new((0..24).map(|v| v as f32).collect(), &[2, 3, 4])));
+        let kept = ops::SumDim::forward(x.clone(), &[1], true);
+        assert_eq!(kept.shape, vec![2, 1, 4]);
+        assert_eq!(kept.buffer, vec![12.0, 15.0, 18.0, 21.0, 48.0, 51.0, 54.0, 57.0]);
+        let dropped = Rc::new(RefCell::new(ops::SumDim::forward(x.clone(), &[0, 2], false)));
+        assert_eq!(dropped.borrow().shape, vec![3]);
+        assert_eq!(dropped.borrow().buffer, vec![60.0, 92.0, 124.0]);
+        let weights = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0], &[3])));
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(dropped, weights))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        let grad = x.borrow().grad.clone().unwrap();
+        assert_eq!(*grad.borrow().at_im(&[1, 2, 3]), 3.0);
+        assert_eq!(*grad.borrow().at_im(&[0, 1, 0]), 2.0);
+    }
+
+    #[test]
+    fn mean_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0], &[2, 3])));
+        let mut rows = ops::Mean::forward(x.clone(), &[1], false);
+        assert_eq!(rows.buffer, vec![2.0, 5.0]);
+        rows.backward(Rc::new(RefCell::new(Tensor::new(vec![3.0, 6.0], &[2]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 1.0, 1.0, 2.0, 2.0, 2.0]);
+        let (_, tangent) = forward_ad::jvp(|p| ops::Mean::forward(p[0].clone(), &[0, 1], true), std::slice::from_ref(&x), vec![Tensor::constant_fill(2.0, &[2, 3])]);
+        assert_eq!(tangent.shape, vec![1, 1]);
+        assert_eq!(tangent.buffer, vec![2.0]);
+    }
+
+    #[test]
+    fn max_min_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 5.0, 5.0, 7.0, -2.0, 0.0], &[2, 3])));
+        let mut maxima = ops::Max::forward(x.clone(), &[1], true);
+        assert_eq!(maxima.shape, vec![2, 1]);
+        assert_eq!(maxima.buffer, vec![5.0, 7.0]);
+        maxima.backward(Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0], &[2, 1]))));
+        // the tie between the two fives goes to the first one
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![0.0, 1.0, 0.0, 2.0, 0.0, 0.0]);
+        x.borrow_mut().grad = None;
+        let mut minima = ops::Min::forward(x.clone(), &[0], false);
+        assert_eq!(minima.buffer, vec![1.0, -2.0, 0.0]);
+        minima.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[3]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 0.0, 0.0, 0.0, 1.0, 1.0]);
+        let overall = ops::Max::forward_nograd(x.clone(), &[0, 1], false);
+        assert_eq!(overall.item(), 7.0);
+    }
+
+    #[test]
+    fn argmax_test() {
+        let x = Tensor::new(vec![1.0, 5.0, 5.0, 7.0, -2.0, 0.0], &[2, 3]);
+        assert_eq!(x.argmax(1, false).buffer, vec![1, 0]);
+        assert_eq!(x.argmin(1, false).buffer, vec![0, 1]);
+        let columns = x.argmax(0, true);
+        assert_eq!(columns.shape, vec![1, 3]);
+        assert_eq!(columns.buffer, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn max_min_nan_test() {
+        // the value, the grad and the index all pick the first NaN wherever it sits
+        for position in 0..3 {
+            let mut values = vec![1.0, 3.0, 2.0];
+            values[position] = f32::NAN;
+            let x = Rc::new(RefCell::new(Tensor::new(values, &[3])));
+            let mut expected = vec![0.0; 3];
+            expected[position] = 1.0;
+            let mut maximum = ops::Max::forward(x.clone(), &[0], false);
+            assert!(maximum.item().is_nan());
+            maximum.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+            assert_eq!(x.borrow_mut().grad.take().unwrap().borrow().buffer, expected);
+            let mut minimum = ops::Min::forward(x.clone(), &[0], false);
+            assert!(minimum.item().is_nan());
+            minimum.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+            assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, expected);
+            assert_eq!(x.borrow().argmax(0, false).item(), position as i64);
+            assert_eq!(x.borrow().argmin(0, false).item(), position as i64);
+        }
+        let x = Tensor::new(vec![f32::NAN, 1.0, 2.0, f32::NAN], &[2, 2]);
+        assert_eq!(x.argmax(1, false).buffer, vec![0, 1]);
+        assert!(ops::Max::forward_nograd(Rc::new(RefCell::new(x)), &[1], false).buffer.iter().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn prod_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![2.0, 3.0, 4.0, 0.0, 5.0, 6.0], &[2, 3])));
+        let mut products = ops::Prod::forward(x.clone(), &[1], false);
+        assert_eq!(products.buffer, vec![24.0, 0.0]);
+        products.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![12.0, 8.0, 6.0, 30.0, 0.0, 0.0]);
+
+        // d2/dx2 of x*y*z is zero on the diagonal, second derivative through the division form
+        let y = Rc::new(RefCell::new(Tensor::new(vec![2.0, 3.0, 4.0], &[3])));
+        let mut product = ops::Prod::forward(y.clone(), &[0], false);
+        product.backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), true);
+        let first = y.borrow_mut().grad.take().unwrap();
+        assert_eq!(first.borrow().buffer, vec![12.0, 8.0, 6.0]);
+        let mut total = ops::Sum::forward(first);
+        total.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        // d/dx (yz + xz + xy) = y + z
+        assert_eq!(y.borrow().grad.as_ref().unwrap().borrow().buffer, vec![7.0, 6.0, 5.0]);
+
+        // the same through a zero, where the division form does not apply
+        let z = Rc::new(RefCell::new(tensor![2.0, 0.0, 4.0]));
+        let mut product = ops::Prod::forward(z.clone(), &[0], false);
+        product.backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), true);
+        let first = z.borrow_mut().grad.take().unwrap();
+        assert_eq!(first.borrow().values().to_vec(), vec![0.0, 8.0, 0.0]);
+        ops::Sum::forward(first).backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(z.borrow().grad.as_ref().unwrap().borrow().values().to_vec(), vec![4.0, 6.0, 2.0]);
+
+        let zeros = Tensor::new(vec![0.5, 0.0, 2.0, -1.0, 1.5, 3.0, 0.0, 0.0, 1.0, 2.0, -0.5, 1.0], &[2, 3, 2]);
+        check_gradients(|p| ops::Prod::forward(p[0].clone(), &[0, 2], false), vec![zeros.clone()]);
+        check_gradients(|p| ops::Prod::forward(p[0].clone(), &[2, 1], true), vec![zeros]);
+    }
+
+    #[test]
+    fn cat_test() {
+        let a = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2])));
+        let b = Rc::new(RefCell::new(Tensor::new(vec![5.0, 6.0], &[2, 1])));
+        let joined = Rc::new(RefCell::new(ops::Cat::forward(vec![a.clone(), b.clone()], 1)));
+        assert_eq!(joined.borrow().shape, vec![2, 3]);
+        assert_eq!(joined.borrow().buffer, vec![1.0, 2.0, 5.0, 3.0, 4.0, 6.0]);
+        let weights = Rc::new(RefCell::new(Tensor::new((1..7).map(|v| v as f32).collect(), &[2, 3])));
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(joined, weights))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 2.0, 4.0, 5.0]);
+        assert_eq!(b.borrow().grad.as_ref().unwrap().borrow().buffer, vec![3.0, 6.0]);
+
+        let rows = ops::Cat::forward_nograd(vec![a.clone(), a], 0);
+        assert_eq!(rows.shape, vec![4, 2]);
+        assert_eq!(rows.buffer, vec![1.0, 2.0, 3.0, 4.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn skip_connection_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![-1.0, 2.0], &[1, 2])));
+        let activated = Rc::new(RefCell::new(ops::Relu::forward(x.clone())));
+        let mut joined = ops::Cat::forward(vec![x.clone(), activated], 1);
+        assert_eq!(joined.buffer, vec![-1.0, 2.0, 0.0, 2.0]);
+        joined.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[1, 4]))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn stack_test() {
+        let rows: Vec<Rc<RefCell<Tensor>>> = (0..3).map(|row| Rc::new(RefCell::new(Tensor::new(vec![row as f32, 10.0 + row as f32], &[2])))).collect();
+        let batch = Rc::new(RefCell::new(ops::Stack::forward(rows.clone(), 0)));
+        assert_eq!(batch.borrow().shape, vec![3, 2]);
+        assert_eq!(batch.borrow().buffer, vec![0.0, 10.0, 1.0, 11.0, 2.0, 12.0]);
+        let columns = ops::Stack::forward_nograd(rows.clone(), 1);
+        assert_eq!(columns.shape, vec![2, 3]);
+        assert_eq!(columns.buffer, vec![0.0, 1.0, 2.0, 10.0, 11.0, 12.0]);
+        let mut result = ops::L2norm::forward(batch);
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(rows[2].borrow().grad.as_ref().unwrap().borrow().buffer, vec![4.0, 24.0]);
+    }
+
+    #[test]
+    fn split_chunk_test() {
+        let x = Rc::new(RefCell::new(Tensor::new((0..10).map(|v| v as f32).collect(), &[5, 2])));
+        let parts = ops::split(x.clone(), 0, &[1, 4]);
+        assert_eq!(parts[0].values().to_vec(), vec![0.0, 1.0]);
+        assert_eq!(parts[1].shape, vec![4, 2]);
+        let chunks = ops::chunk(x.clone(), 0, 2);
+        assert_eq!(chunks.iter().map(|part| part.shape[0]).collect::<Vec<u32>>(), vec![3, 2]);
+        let halves = ops::chunk(x.clone(), 1, 2);
+        assert_eq!(halves[1].values().to_vec(), vec![1.0, 3.0, 5.0, 7.0, 9.0]);
+        // a gated unit, one half scales the other
+        let mut halves = halves.into_iter().map(|half| Rc::new(RefCell::new(half)));
+        let (value, gate) = (halves.next().unwrap(), halves.next().unwrap());
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(value, gate))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 0.0, 3.0, 2.0, 5.0, 4.0, 7.0, 6.0, 9.0, 8.0]);
+    }
+
+    /// Compares the grads of `sum(f(inputs))` from backward against central differences, and
+    /// the forward-mode derivative along a fixed direction against the same grads.
+    fn check_gradients(f: impl Fn(&[Rc<RefCell<Tensor>>]) -> Tensor, inputs: Vec<Tensor>) {
+        let eps = 1e-2;
+        let total = |inputs: &[Tensor]| -> f32 {
+            let _guard = grad_mode::no_grad_guard();
+            let x: Vec<Rc<RefCell<Tensor>>> = inputs.iter().map(|input| Rc::new(RefCell::new(input.clone()))).collect();
+            f(&x).values().iter().sum()
+        };
+        let x: Vec<Rc<RefCell<Tensor>>> = inputs.iter().map(|input| Rc::new(RefCell::new(input.clone()))).collect();
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(f(&x))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        let mut directional = 0.0;
+        let mut tangents: Vec<Tensor> = Vec::new();
+        for (idx, input) in inputs.iter().enumerate() {
+            let grad = x[idx].borrow().grad.as_ref().unwrap().borrow().values().to_vec();
+            let direction: Vec<f32> = (0..input.numel()).map(|j| 0.5 - 0.25*j as f32).collect();
+            for (j, (&analytic, &d)) in grad.iter().zip(direction.iter()).enumerate() {
+                let mut plus = inputs.clone();
+                plus[idx].buffer[j] += eps;
+                let mut minus = inputs.clone();
+                minus[idx].buffer[j] -= eps;
+                let numeric = (total(&plus) - total(&minus))/(2.0*eps);
+                assert!((numeric - analytic).abs() < 1e-2*analytic.abs().max(1.0), "input {} element {}: backward gave {}, finite differences {}", idx, j, analytic, numeric);
+                directional += analytic*d;
+            }
+            tangents.push(Tensor::new(direction, &input.shape));
+        }
+        let primals: Vec<Rc<RefCell<Tensor>>> = inputs.iter().map(|input| Rc::new(RefCell::new(input.clone()))).collect();
+        let (_, tangent) = forward_ad::jvp(|p| ops::Sum::forward(Rc::new(RefCell::new(f(p)))), &primals, tangents);
+        assert!((tangent.item() - directional).abs() < 1e-3*directional.abs().max(1.0), "jvp gave {}, backward {}", tangent.item(), directional);
+    }
+
+    #[test]
+    fn unary_math_gradient_test() {
+        let x = Tensor::new(vec![0.3, -1.2, 2.0, 0.7, -0.4, 1.5], &[2, 3]);
+        let positive = Tensor::new(vec![0.3, 1.2, 2.0, 0.7, 0.4, 1.5], &[2, 3]);
+        check_gradients(|p| ops::Neg::forward(p[0].clone()), vec![x.clone()]);
+        check_gradients(|p| ops::Exp::forward(p[0].clone()), vec![x.clone()]);
+        check_gradients(|p| ops::Sqrt::forward(p[0].clone()), vec![positive.clone()]);
+        check_gradients(|p| ops::PowScalar::forward(p[0].clone(), 3.0), vec![x.clone()]);
+        check_gradients(|p| ops::PowScalar::forward(p[0].clone(), -0.5), vec![positive.clone()]);
+        check_gradients(|p| ops::Abs::forward(p[0].clone()), vec![x.clone()]);
+        check_gradients(|p| ops::Sin::forward(p[0].clone()), vec![x.clone()]);
+        check_gradients(|p| ops::Cos::forward(p[0].clone()), vec![x.clone()]);
+        check_gradients(|p| ops::Tanh::forward(p[0].clone()), vec![x.clone()]);
+        check_gradients(|p| ops::Sigmoid::forward(p[0].clone()), vec![x.clone()]);
+        check_gradients(|p| ops::Clamp::forward(p[0].clone(), -1.0, 1.0), vec![x.clone()]);
+        check_gradients(|p| ops::Reciprocal::forward(p[0].clone()), vec![positive]);
+    }
+
+    #[test]
+    fn binary_math_gradient_test() {
+        let a = Tensor::new(vec![0.5, 1.5, 2.0, 0.8, 1.1, 3.0], &[2, 3]);
+        let b = Tensor::new(vec![1.0, -2.0, 0.5], &[3]);
+        check_gradients(|p| ops::Div::forward(p[0].clone(), p[1].clone()), vec![a.clone(), b.clone()]);
+        check_gradients(|p| ops::Pow::forward(p[0].clone(), p[1].clone()), vec![a.clone(), b.clone()]);
+        check_gradients(|p| ops::Maximum::forward(p[0].clone(), p[1].clone()), vec![a.clone(), b.clone()]);
+        check_gradients(|p| ops::Minimum::forward(p[0].clone(), p[1].clone()), vec![a, b]);
+    }
+
+    #[test]
+    fn math_nograd_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![-2.0, 0.0, 4.0], &[3])));
+        let y = Rc::new(RefCell::new(Tensor::new(vec![1.0, 1.0, 1.0], &[3])));
+        let cases: Vec<(Tensor, Tensor)> = vec![
+            (ops::Neg::forward(x.clone()), ops::Neg::forward_nograd(x.clone())),
+            (ops::Abs::forward(x.clone()), ops::Abs::forward_nograd(x.clone())),
+            (ops::Clamp::forward(x.clone(), -1.0, 1.0), ops::Clamp::forward_nograd(x.clone(), -1.0, 1.0)),
+            (ops::Maximum::forward(x.clone(), y.clone()), ops::Maximum::forward_nograd(x.clone(), y.clone())),
+            (ops::Minimum::forward(x.clone(), y.clone()), ops::Minimum::forward_nograd(x.clone(), y.clone())),
+        ];
+        let expected: Vec<Vec<f32>> = vec![vec![2.0, -0.0, -4.0], vec![2.0, 0.0, 4.0], vec![-1.0, 0.0, 1.0], vec![1.0, 1.0, 4.0], vec![-2.0, 0.0, 1.0]];
+        for ((recorded, plain), values) in cases.iter().zip(expected) {
+            assert_eq!(recorded.buffer, values);
+            assert_eq!(plain.buffer, values);
+            assert!(recorded.op.is_some());
+            assert!(plain.op.is_none() && plain.children.is_empty());
+        }
+        let sqrt = ops::Sqrt::forward_nograd(Rc::new(RefCell::new(Tensor::new(vec![4.0, 9.0], &[2]))));
+        assert_eq!(sqrt.buffer, vec![2.0, 3.0]);
+        assert_eq!(ops::Exp::forward_nograd(Rc::new(RefCell::new(Tensor::scalar(0.0)))).item(), 1.0);
+    }
+
+    #[test]
+    fn maximum_tie_test() {
+        let a = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0], &[2])));
+        let b = Rc::new(RefCell::new(Tensor::new(vec![1.0, 3.0], &[2])));
+        let mut result = ops::Maximum::forward(a.clone(), b.clone());
+        result.backward(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2]))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![0.5, 0.0]);
+        assert_eq!(b.borrow().grad.as_ref().unwrap().borrow().buffer, vec![0.5, 1.0]);
+    }
+
+    #[test]
+    fn math_second_derivative_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![0.5, 1.0], &[2])));
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Sin::forward(x.clone()))));
+        result.backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), true);
+        let first = x.borrow_mut().grad.take().unwrap();
+        let mut total = ops::Sum::forward(first);
+        total.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        let second = x.borrow().grad.as_ref().unwrap().borrow().buffer.clone();
+        assert_eq!(second, vec![-0.5f32.sin(), -1.0f32.sin()]);
+    }
+
+    #[test]
+    fn var_operator_test() {
+        let a: var::Var = var::Var::new(Tensor::new(vec![1.0, 2.0, 3.0], &[3]));
+        let b = var::Var::new(Tensor::new(vec![4.0, 5.0, 6.0], &[3]));
+        let result = (&a * &b + 2.0 * &a - &b / 2.0 + (-&a)).sum();
+        // sum(a*b + a - b/2)
+        assert_eq!(result.item(), 32.0 + 6.0 - 7.5);
+        result.backward();
+        assert_eq!(a.grad().unwrap().to_vec(), vec![5.0, 6.0, 7.0]);
+        assert_eq!(b.grad().unwrap().to_vec(), vec![0.5, 1.5, 2.5]);
+        let shifted = 1.0 - a.clone();
+        assert_eq!(shifted.to_vec(), vec![0.0, -1.0, -2.0]);
+        let quotient = 6.0 / a;
+        assert_eq!(quotient.to_vec(), vec![6.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn var_chaining_test() {
+        let x = var::Var::new(Tensor::new(vec![1.0, 2.0], &[1, 2]));
+        let w = var::Var::new(Tensor::new(vec![1.0, -1.0, 2.0, 1.0], &[2, 2]));
+        let y = x.matmul(&w).relu().log();
+        assert_eq!(y.shape(), vec![1, 2]);
+        assert_eq!(y.to_vec(), vec![5.0f32.ln(), 1.0f32.ln()]);
+        y.sum().backward();
+        // d/dw log(x.w) = x / (x.w) per column
+        assert_eq!(w.grad().unwrap().to_vec(), vec![0.2, 1.0, 0.4, 2.0]);
+        let rows = var::Var::stack(&[x.clone(), x.clone()], 0).squeeze(1).mean(&[0], false);
+        assert_eq!(rows.to_vec(), vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn try_constructor_test() {
+        let err = Tensor::try_new(vec![1.0, 2.0, 3.0], &[2, 2]).err().unwrap();
+        assert_eq!(err, error::RsgradError::ShapeMismatch {op: "new".to_string(), lhs: vec![3], rhs: vec![2, 2]});
+        assert!(Tensor::try_new(vec![1.0, 2.0], &[2]).is_ok());
+        let mut x = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2]);
+        assert_eq!(x.try_at(&[1, 2]).err(), Some(error::RsgradError::IndexOutOfRange {index: 2, size: 2}));
+        assert_eq!(x.try_at_im(&[1]).err(), Some(error::RsgradError::RankMismatch {op: "at".to_string(), expected: 2, got: 1}));
+        *x.try_at(&[1, 1]).unwrap() = 5.0;
+        assert_eq!(*x.try_at_im(&[1, 1]).unwrap(), 5.0);
+        assert!(matches!(Tensor::new(vec![1.0], &[1]).try_transpose(), Err(error::RsgradError::RankMismatch {..})));
+        assert!(x.try_reshape(&[3]).is_err());
+        assert!(x.try_squeeze(0).is_err());
+        assert!(x.try_item().is_err());
+        assert!(matches!(x.try_grad(), Err(error::RsgradError::MissingGradient)));
+    }
+
+    #[test]
+    fn try_op_test() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2, 3])));
+        let b = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2, 3])));
+        let err = ops::MatMul::try_forward(a.clone(), b.clone()).err().unwrap();
+        assert_eq!(err, error::RsgradError::ShapeMismatch {op: "MatMul".to_string(), lhs: vec![2, 3], rhs: vec![2, 3]});
+        assert_eq!(err.to_string(), "MatMul: shapes [2, 3] and [2, 3] do not match");
+        let scalar = Rc::new(RefCell::new(Tensor::scalar(1.0)));
+        assert!(matches!(ops::MatMul::try_forward(a.clone(), scalar), Err(error::RsgradError::RankMismatch {expected: 2, got: 0, ..})));
+        let c = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[4])));
+        assert!(matches!(ops::Add::try_forward(a.clone(), c.clone()), Err(error::RsgradError::ShapeMismatch {..})));
+        assert!(ops::Add::try_forward(a.clone(), b.clone()).is_ok());
+        assert!(ops::SumDim::try_forward(a.clone(), &[2], false).is_err());
+        assert_eq!(ops::Mean::try_forward(a.clone(), &[1, 1], false).unwrap_err(), error::RsgradError::invalid("reduce", "dimension 1 is reduced twice"));
+        assert!(ops::Cat::try_forward(vec![a.clone(), c.clone()], 0).is_err());
+        assert!(ops::IndexSelect::try_forward(a.clone(), 0, &[2]).is_err());
+        assert!(matches!(ops::Slice::try_forward(a.clone(), 0, 0, 2, 0), Err(error::RsgradError::InvalidArgument {..})));
+        let negative = Tensor::new(vec![-1, 1, 0], &[1, 3]);
+        assert_eq!(ops::Gather::try_forward(a.clone(), 0, &negative).unwrap_err(), error::RsgradError::invalid("Gather", "negative index -1"));
+        let index = Tensor::new(vec![0, 1, 2], &[1, 3]);
+        assert!(ops::Gather::try_forward(a.clone(), 0, &index).is_err());
+        assert_eq!(ops::Gather::try_forward(a.clone(), 1, &index).unwrap().shape, vec![1, 3]);
+        assert!(ops::try_split(a.clone(), 1, &[1, 1]).is_err());
+        assert!(matches!(ops::try_chunk(a.clone(), 1, 0), Err(error::RsgradError::InvalidArgument {..})));
+        assert_eq!(ops::try_chunk(a, 1, 2).unwrap().len(), 2);
+        let v = var::Var::new(Tensor::scalar(1.0));
+        assert!(v.try_grad().is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "MatMul: shapes [2, 3] and [2, 3] do not match")]
+    fn panicking_op_message_test() {
+        let a = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2, 3])));
+        ops::MatMul::forward(a.clone(), a);
+    }
+
+    #[test]
+    fn try_backward_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![1.0, 2.0], &[2])));
+        let mut doubled = ops::Mult::forward(x.clone(), Rc::new(RefCell::new(Tensor::scalar(2.0))));
+        assert!(doubled.try_backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), false).is_err());
+        assert!(x.borrow().grad.is_none());
+        doubled.try_backward_with(Rc::new(RefCell::new(Tensor::constant_fill(1.0, &[2]))), false).unwrap();
+        assert_eq!(x.borrow().try_grad().unwrap().borrow().buffer, vec![2.0, 2.0]);
+    }
+
+    #[test]
+    fn f64_gradient_test() {
+        // in f64 central differences agree with backward to far more digits than f32 allows
+        let x: var::Var<f64> = var::Var::new(Tensor::new(vec![0.3, -1.2, 2.0], &[3]));
+        let f = |x: &var::Var<f64>| (x.sigmoid() * x.tanh() + x.exp() / 3.0).sum();
+        f(&x).backward();
+        let grad = x.grad().unwrap().to_vec();
+        let eps = 1e-6;
+        for j in 0..3 {
+            let shifted = |delta: f64| {
+                let mut values = x.to_vec();
+                values[j] += delta;
+                f(&var::Var::new(Tensor::new(values, &[3]))).item()
+            };
+            let numeric = (shifted(eps) - shifted(-eps))/(2.0*eps);
+            assert!((numeric - grad[j]).abs() < 1e-8, "element {}: backward gave {}, finite differences {}", j, grad[j], numeric);
+        }
+        assert_eq!(x.borrow().dtype(), element::DType::F64);
+    }
+
+    #[test]
+    fn half_precision_test() {
+        let a: Tensor<half::f16> = Tensor::new(vec![1.0, 2.0, 3.0, 4.0], &[2, 2]).cast();
+        let b: Tensor<half::f16> = Tensor::new(vec![0.5, -1.0, 0.25, 2.0], &[2, 2]).cast();
+        let (a, b) = (var::Var::new(a.with_requires_grad(true)), var::Var::new(b.with_requires_grad(true)));
+        let product = a.matmul(&b);
+        assert_eq!(product.borrow().cast::<f32>().buffer, vec![1.0, 3.0, 2.5, 5.0]);
+        product.sum().backward();
+        assert_eq!(a.grad().unwrap().borrow().cast::<f32>().buffer, vec![-0.5, 2.25, -0.5, 2.25]);
+        let weights: Tensor<half::bf16> = Tensor::new(vec![1.5, -2.0], &[2]).cast();
+        assert_eq!(weights.dtype(), element::DType::BF16);
+        assert_eq!(ops::Relu::forward_nograd(Rc::new(RefCell::new(weights))).cast::<f64>().buffer, vec![1.5, 0.0]);
+    }
+
+    #[test]
+    fn cast_test() {
+        let x = Rc::new(RefCell::new(Tensor::new(vec![2.7, -1.5, 0.0, 3.0], &[2, 2])));
+        let doubled = ops::Mult::forward(x.clone(), Rc::new(RefCell::new(Tensor::scalar(2.0))));
+        let labels: Tensor<i64> = doubled.cast();
+        assert_eq!(labels.buffer, vec![5, -3, 0, 6]);
+        assert_eq!(labels.shape, vec![2, 2]);
+        assert_eq!(labels.dtype(), element::DType::I64);
+        let mask = x.borrow().cast::<bool>();
+        assert_eq!(mask.buffer, vec![true, true, false, true]);
+        assert_eq!(mask.cast::<f32>().buffer, vec![1.0, 1.0, 0.0, 1.0]);
+        // casts cut the graph
+        let roundtrip = doubled.cast::<f64>().cast::<f32>();
+        assert!(!roundtrip.requires_grad && roundtrip.op.is_none());
+        assert_eq!(x.borrow().transpose().cast::<i32>().buffer, vec![2, 0, -1, 3]);
+    }
+
+    #[test]
+    fn try_cast_test() {
+        let whole = Tensor::new(vec![3.0, 0.0, 255.0], &[3]);
+        assert_eq!(whole.try_cast::<u8>().unwrap().buffer, vec![3, 0, 255]);
+        let err = Tensor::new(vec![2.5], &[1]).try_cast::<i64>().err().unwrap();
+        assert_eq!(err, error::RsgradError::DtypeMismatch {expected: "i64".to_string(), got: "2.5".to_string()});
+        assert!(Tensor::new(vec![256.0], &[1]).try_cast::<u8>().is_err());
+        assert!(Tensor::new(vec![f32::NAN], &[1]).try_cast::<i32>().is_err());
+        assert!(Tensor::new(vec![2.0], &[1]).try_cast::<bool>().is_err());
+        assert!(Tensor::new(vec![1.0e6], &[1]).try_cast::<half::f16>().is_err());
+        assert!(Tensor::new(vec![f32::INFINITY, 0.1], &[2]).try_cast::<half::f16>().is_ok());
+    }
+
+    #[test]
+    fn constructors_test() {
+        let zeros: Tensor<i64> = Tensor::zeros(&[2, 3]);
+        assert_eq!(zeros.buffer, vec![0; 6]);
+        let ones: Tensor = Tensor::ones(&[2]);
+        assert_eq!(ones.buffer, vec![1.0, 1.0]);
+        let x = tensor![[1.0, 2.0], [3.0, 4.0]].transpose();
+        assert_eq!(x.zeros_like().shape, vec![2, 2]);
+        let filled = x.full_like(7.5);
+        assert_eq!(filled.buffer, vec![7.5; 4]);
+        assert!(filled.is_contiguous());
+        assert_eq!(Tensor::arange(0, 5, 2).buffer, vec![0, 2, 4]);
+        assert_eq!(Tensor::arange(1.0, 0.0, -0.25).buffer, vec![1.0, 0.75, 0.5, 0.25]);
+        assert_eq!(Tensor::arange(3.0, 1.0, 1.0).shape, vec![0]);
+        assert_eq!(Tensor::<i64>::try_arange(0, 5, 0).unwrap_err(), error::RsgradError::invalid("arange", "step cannot be 0"));
+        assert_eq!(Tensor::linspace(0.0, 1.0, 5).buffer, vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(Tensor::linspace(0.1f32, 0.7, 3).buffer, vec![0.1, 0.4, 0.7]);
+        assert_eq!(Tensor::linspace(2.0, 3.0, 1).buffer, vec![2.0]);
+        let eye: Tensor = Tensor::eye(3);
+        assert_eq!(eye.buffer, vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    fn nested_test() {
+        let matrix = tensor![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        assert_eq!(matrix.shape, vec![2, 3]);
+        assert_eq!(matrix.stride, vec![3, 1]);
+        assert_eq!(matrix.buffer, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let cube = Tensor::from_nested(vec![vec![[1, 2], [3, 4]], vec![[5, 6], [7, 8]], vec![[9, 10], [11, 12]]]);
+        assert_eq!(cube.shape, vec![3, 2, 2]);
+        assert_eq!(*cube.at_im(&[2, 0, 1]), 10);
+        assert_eq!(tensor![true, false].dtype(), element::DType::Bool);
+        assert_eq!(Tensor::from_nested(4.0).shape, Vec::<u32>::new());
+        let empty: Tensor = Tensor::from_nested(Vec::<f32>::new());
+        assert_eq!(empty.shape, vec![0]);
+        let ragged = Tensor::try_from_nested(vec![vec![1.0, 2.0], vec![3.0]]);
+        assert!(matches!(ragged, Err(error::RsgradError::ShapeMismatch {lhs, rhs, ..}) if lhs == vec![1] && rhs == vec![2]));
+        assert!(Tensor::try_from_nested(vec![vec![vec![1.0]], vec![]]).is_err());
+    }
+
+    #[test]
+    fn seeded_random_test() {
+        let mut first = random::Generator::new(7);
+        let mut second = random::Generator::new(7);
+        let a: Tensor = first.normal(0.0, 1.0, &[16]);
+        let b: Tensor = second.normal(0.0, 1.0, &[16]);
+        assert_eq!(a.buffer, b.buffer);
+        random::manual_seed(3);
+        let c: Tensor = Tensor::rand(&[8]);
+        random::manual_seed(3);
+        let d: Tensor = Tensor::rand(&[8]);
+        assert_eq!(c.buffer, d.buffer);
+        assert!(c.values().iter().all(|&value| (0.0..1.0).contains(&value)));
+    }
+
+    #[test]
+    fn distributions_test() {
+        let mut generator = random::Generator::new(0);
+        let uniform: Tensor<f64> = generator.uniform(-2.0, 3.0, &[1000]);
+        assert!(uniform.values().iter().all(|&value| (-2.0..3.0).contains(&value)));
+        assert!((uniform.values().iter().sum::<f64>()/1000.0 - 0.5).abs() < 0.2);
+        let normal: Tensor<f64> = generator.normal(1.0, 2.0, &[4000]);
+        let mean = normal.values().iter().sum::<f64>()/4000.0;
+        let var = normal.values().iter().map(|&value| (value - mean).powi(2)).sum::<f64>()/4000.0;
+        assert!((mean - 1.0).abs() < 0.15);
+        assert!((var.sqrt() - 2.0).abs() < 0.15);
+        let truncated: Tensor = generator.truncated_normal(0.0, 1.0, -0.5, 2.0, &[500]);
+        assert!(truncated.values().iter().all(|&value| (-0.5..=2.0).contains(&value)));
+        let pinned: Tensor = generator.truncated_normal(0.0, 1.0, 3.0, 3.0, &[4]);
+        assert_eq!(pinned.buffer, vec![3.0; 4]);
+        assert!(matches!(generator.try_uniform::<f32>(1.0, 0.0, &[2]), Err(error::RsgradError::InvalidArgument {..})));
+        assert!(matches!(generator.try_normal::<f32>(0.0, -1.0, &[2]), Err(error::RsgradError::InvalidArgument {..})));
+        assert!(matches!(generator.try_truncated_normal::<f32>(2.0, 0.0, -1.0, 1.0, &[2]), Err(error::RsgradError::InvalidArgument {..})));
+        assert!(random::try_uniform::<f32>(0.0, f64::NAN, &[2]).is_err());
+        let coins = generator.bernoulli(&tensor![0.0, 1.0, 0.0]);
+        assert_eq!(coins.buffer, vec![false, true, false]);
+        assert_eq!(generator.try_bernoulli(&tensor![1.5]).unwrap_err(), error::RsgradError::invalid("bernoulli", "probability 1.5 is not in [0, 1]"));
+        let draws = generator.multinomial(&tensor![[0.0, 1.0, 1.0], [2.0, 0.0, 2.0]], 2, false);
+        assert_eq!(draws.shape, vec![2, 2]);
+        let mut rows: Vec<Vec<i64>> = draws.values().into_owned().chunks(2).map(|row| row.to_vec()).collect();
+        rows.iter_mut().for_each(|row| row.sort());
+        assert_eq!(rows, vec![vec![1, 2], vec![0, 2]]);
+        assert!(matches!(generator.try_multinomial(&tensor![0.0, 1.0], 2, false), Err(error::RsgradError::InvalidArgument {..})));
+        assert!(matches!(generator.try_multinomial(&tensor![-1.0, 1.0], 1, true), Err(error::RsgradError::InvalidArgument {..})));
+        assert!(matches!(generator.try_multinomial(&Tensor::<f32>::zeros(&[2, 0]), 1, true), Err(error::RsgradError::InvalidArgument {..})));
+        let counts = generator.multinomial(&tensor![1.0, 3.0], 4000, true).values().iter().filter(|&&index| index == 1).count();
+        assert!((counts as f64/4000.0 - 0.75).abs() < 0.05);
+        let mut perm = generator.randperm(10).values().into_owned();
+        perm.sort();
+        assert_eq!(perm, (0..10).collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn display_test() {
+        let matrix: Tensor = tensor![[1.0, -2.5], [30.0, 4.0]];
+        assert_eq!(format!("{}", matrix), "tensor([[ 1.0000, -2.5000],\n        [30.0000,  4.0000]], shape=[2, 2], dtype=f32)");
+        assert_eq!(format!("{:.1}", matrix.transpose()), "tensor([[ 1.0, 30.0],\n        [-2.5,  4.0]], shape=[2, 2], dtype=f32)");
+        assert_eq!(format!("{}", tensor![[[1, 2]], [[3, 4]]]), "tensor([[[1, 2]],\n\n        [[3, 4]]], shape=[2, 1, 2], dtype=i32)");
+        assert_eq!(format!("{}", tensor![true, false]), "tensor([ true, false], shape=[2], dtype=bool)");
+        assert_eq!(format!("{}", Tensor::scalar(2.0f64)), "tensor(2.0000, shape=[], dtype=f64)");
+        let long: Tensor<i64> = Tensor::arange(0, 2000, 1);
+        assert_eq!(format!("{}", long), "tensor([   0,    1,    2, ..., 1997, 1998, 1999], shape=[2000], dtype=i64)");
+        display::set_print_options(display::PrintOptions {precision: 2, threshold: 4, edge_items: 1});
+        let grid: Tensor = Tensor::arange(0.0, 9.0, 1.0).reshape(&[3, 3]);
+        assert_eq!(format!("{}", grid), "tensor([[0.00, ..., 2.00],\n        ...,\n        [6.00, ..., 8.00]], shape=[3, 3], dtype=f32)");
+        display::set_print_options(display::PrintOptions::default());
+    }
+
+    #[test]
+    fn debug_test() {
+        let a = Rc::new(RefCell::new(tensor![1.0f32, 2.0]));
+        let b = Rc::new(RefCell::new(tensor![3.0, 4.0]));
+        let mut c = ops::Mult::forward(a.clone(), b);
+        c.backward(Rc::new(RefCell::new(Tensor::ones(&[2]))));
+        assert_eq!(format!("{:?}", c), "Tensor { shape: [2], stride: [1], offset: 0, dtype: f32, op: Some(\"Mult\"), children: 2, grad: true, requires_grad: true }");
+        assert_eq!(format!("{:?}", a.borrow()), "Tensor { shape: [2], stride: [1], offset: 0, dtype: f32, op: None, children: 0, grad: true, requires_grad: true }");
+    }
+
+    #[test]
+    fn comparison_test() {
+        let a: Tensor = tensor![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let b: Tensor = tensor![2.0, 2.0, 7.0];
+        assert_eq!(a.eq(&b).buffer, vec![false, true, false, false, false, false]);
+        assert_eq!(a.ne(&b).buffer, vec![true, false, true, true, true, true]);
+        assert_eq!(a.lt(&b).buffer, vec![true, false, true, false, false, true]);
+        assert_eq!(a.le(&b).buffer, vec![true, true, true, false, false, true]);
+        assert_eq!(a.gt(&Tensor::scalar(3.0)).buffer, vec![false, false, false, true, true, true]);
+        assert_eq!(a.ge(&b).shape, vec![2, 3]);
+        assert!(a.try_lt(&tensor![1.0, 2.0]).is_err());
+        let indices: Tensor<i64> = tensor![0, 3, 5];
+        assert_eq!(indices.lt(&Tensor::scalar(4)).buffer, vec![true, true, false]);
+        let x = tensor![true, true, false, false];
+        let y = tensor![true, false, true, false];
+        assert_eq!(x.logical_and(&y).buffer, vec![true, false, false, false]);
+        assert_eq!(x.logical_or(&y).buffer, vec![true, true, true, false]);
+        assert_eq!(x.logical_xor(&y).buffer, vec![false, true, true, false]);
+        assert_eq!(x.logical_not().buffer, vec![false, false, true, true]);
+        assert!(!x.logical_not().requires_grad);
+    }
+
+    #[test]
+    fn where_masked_fill_test() {
+        let cond = tensor![true, false, true];
+        let a = Rc::new(RefCell::new(tensor![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]]));
+        let b = Rc::new(RefCell::new(Tensor::scalar(0.0)));
+        let picked = cond.where_cond(a.clone(), b.clone());
+        assert_eq!(picked.buffer, vec![1.0, 0.0, 3.0, 4.0, 0.0, 6.0]);
+        assert!(cond.try_where_cond(a.clone(), Rc::new(RefCell::new(tensor![1.0, 2.0]))).is_err());
+        let x = Tensor::new(vec![0.3, -1.2, 2.0, 0.7, -0.4, 1.5], &[2, 3]);
+        let y = Tensor::new(vec![1.0, -2.0, 0.5], &[3]);
+        check_gradients(|p| ops::Where::forward(&tensor![[true, false, false], [false, true, true]], p[0].clone(), p[1].clone()), vec![x.clone(), y]);
+        check_gradients(|p| ops::MaskedFill::forward(p[0].clone(), &tensor![false, true, false], p[1].clone()), vec![x.clone(), Tensor::scalar(10.0)]);
+        let filled = ops::MaskedFill::forward(a.clone(), &tensor![[true], [false]], Rc::new(RefCell::new(Tensor::scalar(f32::NEG_INFINITY))));
+        assert_eq!(filled.buffer, vec![f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY, 4.0, 5.0, 6.0]);
+        assert!(ops::MaskedFill::try_forward(a.clone(), &tensor![true, false], b.clone()).is_err());
+        assert!(ops::MaskedFill::try_forward(a.clone(), &tensor![true, false, true], Rc::new(RefCell::new(tensor![0.0]))).is_err());
+
+        // Huber loss with delta 1: quadratic inside the band, linear outside of it
+        let diff: var::Var = var::Var::new(tensor![0.5, -3.0, 2.0]);
+        let small = diff.abs().borrow().lt(&Tensor::scalar(1.0));
+        let loss = var::Var::where_cond(&small, &(diff.powf(2.0)*0.5), &(diff.abs() - 0.5)).sum();
+        assert_eq!(loss.item(), 0.125 + 2.5 + 1.5);
+        loss.backward();
+        assert_eq!(diff.grad().unwrap().to_vec(), vec![0.5, -1.0, 1.0]);
+        let fill: var::Var = var::Var::new(Tensor::scalar(0.0));
+        let masked = var::Var::new(tensor![1.0, 2.0, 3.0]).masked_fill(&tensor![false, true, true], &fill);
+        assert_eq!(masked.to_vec(), vec![1.0, 0.0, 0.0]);
+        masked.sum().backward();
+        assert_eq!(fill.grad().unwrap().item(), 2.0);
+    }
+
+    #[test]
+    fn sort_topk_test() {
+        let x: Tensor = tensor![[3.0, 1.0, 2.0, 1.0], [0.5, f32::NAN, -1.0, 4.0]];
+        assert_eq!(x.argsort(1, false).buffer, vec![1, 3, 2, 0, 2, 0, 3, 1]);
+        assert_eq!(x.argsort(1, true).buffer, vec![0, 2, 1, 3, 1, 3, 0, 2]);
+        assert_eq!(x.argsort(0, false).buffer, vec![1, 0, 1, 0, 0, 1, 0, 1]);
+        assert!(x.try_argsort(2, false).is_err());
+        let a = Rc::new(RefCell::new(tensor![[3.0, 1.0, 2.0], [0.5, 6.0, -1.0]]));
+        let (values, indices) = ops::sort(a.clone(), 1, false);
+        assert_eq!(values.buffer, vec![1.0, 2.0, 3.0, -1.0, 0.5, 6.0]);
+        assert_eq!(indices.buffer, vec![1, 2, 0, 2, 0, 1]);
+        let (top, top_indices) = ops::topk(a.clone(), 2, 1, true);
+        assert_eq!(top.shape, vec![2, 2]);
+        assert_eq!(top.buffer, vec![3.0, 2.0, 6.0, 0.5]);
+        assert_eq!(top_indices.buffer, vec![0, 2, 1, 0]);
+        let weights = Rc::new(RefCell::new(tensor![[1.0, 10.0], [100.0, 1000.0]]));
+        let mut result = ops::Sum::forward(Rc::new(RefCell::new(ops::Mult::forward(Rc::new(RefCell::new(top)), weights))));
+        result.backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(a.borrow().grad.as_ref().unwrap().borrow().buffer, vec![1.0, 0.0, 10.0, 1000.0, 100.0, 0.0]);
+        assert!(ops::try_topk(a, 4, 1, true).is_err());
+        let smallest = var::Var::new(tensor![4.0f32, -2.0, 7.0]).topk(1, 0, false).0;
+        assert_eq!(smallest.to_vec(), vec![-2.0]);
+    }
+
+    #[test]
+    fn scan_test() {
+        let x: Tensor = tensor![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let cumsum = ops::CumSum::forward_nograd(Rc::new(RefCell::new(x.clone())), 1, false);
+        assert_eq!(cumsum.buffer, vec![1.0, 3.0, 6.0, 4.0, 9.0, 15.0]);
+        let reverse = ops::CumSum::forward_nograd(Rc::new(RefCell::new(x.clone())), 0, true);
+        assert_eq!(reverse.buffer, vec![5.0, 7.0, 9.0, 4.0, 5.0, 6.0]);
+        let cumprod = ops::CumProd::forward_nograd(Rc::new(RefCell::new(x.clone())), 1);
+        assert_eq!(cumprod.buffer, vec![1.0, 2.0, 6.0, 4.0, 20.0, 120.0]);
+        let logs = ops::LogCumSumExp::forward_nograd(Rc::new(RefCell::new(tensor![0.0f32, 0.0, 1000.0, f32::NEG_INFINITY])), 0);
+        assert!((logs.buffer[1] - 2.0f32.ln()).abs() < 1e-6);
+        assert_eq!(logs.buffer[2], 1000.0);
+        assert_eq!(logs.buffer[3], 1000.0);
+        assert!(ops::CumSum::try_forward(Rc::new(RefCell::new(x)), 2, false).is_err());
+
+        let a = Tensor::new(vec![0.3, -1.2, 2.0, 0.7, -0.4, 1.5], &[2, 3]);
+        check_gradients(|p| ops::CumSum::forward(p[0].clone(), 1, false), vec![a.clone()]);
+        check_gradients(|p| ops::CumSum::forward(p[0].clone(), 0, true), vec![a.clone()]);
+        check_gradients(|p| ops::CumProd::forward(p[0].clone(), 1), vec![a.clone()]);
+        check_gradients(|p| ops::CumProd::forward(p[0].clone(), 1), vec![Tensor::new(vec![0.5, 0.0, 2.0, 0.0, 1.5, 0.0], &[2, 3])]);
+        check_gradients(|p| ops::CumProd::forward(p[0].clone(), 0), vec![Tensor::new(vec![0.5, 0.0, 2.0, 0.0, 1.5, 0.0], &[2, 3])]);
+
+        // second derivatives of x + xy + xyz through the zero
+        let x = Rc::new(RefCell::new(tensor![2.0, 0.0, 3.0]));
+        let mut total = ops::Sum::forward(Rc::new(RefCell::new(ops::CumProd::forward(x.clone(), 0))));
+        total.backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), true);
+        let first = x.borrow_mut().grad.take().unwrap();
+        assert_eq!(first.borrow().values().to_vec(), vec![1.0, 8.0, 0.0]);
+        ops::Sum::forward(first).backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert_eq!(x.borrow().grad.as_ref().unwrap().borrow().values().to_vec(), vec![4.0, 6.0, 2.0]);
+        check_gradients(|p| ops::LogCumSumExp::forward(p[0].clone(), 1), vec![a.clone()]);
+        check_gradients(|p| ops::LogCumSumExp::forward(p[0].clone(), 0), vec![a.clone()]);
+        let weights = Tensor::new(vec![1.0, -2.0, 0.0, 3.0, -0.5, 1.5], &[2, 3]);
+        check_gradients(|p| ops::Mult::forward(Rc::new(RefCell::new(ops::LogCumSumExp::forward(p[0].clone(), 1))), p[1].clone()), vec![a, weights]);
+
+        // masked and far negative elements, where splitting exp(a[i] - out[j]) over- or underflows
+        let grad_of_sum = |values: Vec<f32>| {
+            let x = Rc::new(RefCell::new(Tensor::new(values.clone(), &[values.len() as u32])));
+            ops::Sum::forward(Rc::new(RefCell::new(ops::LogCumSumExp::forward(x.clone(), 0)))).backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+            let grad = x.borrow().grad.as_ref().unwrap().borrow().values().to_vec();
+            grad
+        };
+        let close = |got: Vec<f32>, expected: Vec<f32>| got.iter().zip(expected.iter()).all(|(g, e)| (g - e).abs() < 1e-4);
+        let tail = 1.0 + (-1.0f32).exp()/(1.0 + (-1.0f32).exp());
+        let last = 1.0/(1.0 + (-1.0f32).exp());
+        assert!(close(grad_of_sum(vec![f32::NEG_INFINITY, 0.0, 1.0]), vec![0.0, tail, last]));
+        assert!(close(grad_of_sum(vec![-1e4, 0.0, 1.0]), vec![1.0, tail, last]));
+        assert!(close(grad_of_sum(vec![-100.0, 0.0]), vec![1.0, 1.0]));
+        assert!(close(grad_of_sum(vec![f32::NEG_INFINITY, f32::NEG_INFINITY, 2.0]), vec![0.0, 0.0, 1.0]));
+
+        let masked = Rc::new(RefCell::new(tensor![f32::NEG_INFINITY, 0.0, 1.0]));
+        let (_, tangent) = forward_ad::jvp(|p| ops::LogCumSumExp::forward(p[0].clone(), 0), std::slice::from_ref(&masked), vec![Tensor::ones(&[3])]);
+        assert!(close(tangent.values().to_vec(), vec![0.0, 1.0, 1.0]));
+        // every output has grads summing to one over its inputs, so the second derivative of the
+        // summed grads vanishes, and stays free of NaNs at the masked element
+        let mut total = ops::Sum::forward(Rc::new(RefCell::new(ops::LogCumSumExp::forward(masked.clone(), 0))));
+        total.backward_with(Rc::new(RefCell::new(Tensor::scalar(1.0))), true);
+        let first = masked.borrow_mut().grad.take().unwrap();
+        ops::Sum::forward(first).backward(Rc::new(RefCell::new(Tensor::scalar(1.0))));
+        assert!(close(masked.borrow().grad.as_ref().unwrap().borrow().values().to_vec(), vec![0.0; 3]));
+    }
+
+    #[test]
+    fn einsum_test() {
+        let rc = |tensor: Tensor| Rc::new(RefCell::new(tensor));
+        let a: Tensor = tensor![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let b: Tensor = tensor![[1.0, 0.0], [0.0, 1.0], [2.0, -1.0]];
+        let product = einsum::einsum("ij,jk->ik", vec![rc(a.clone()), rc(b.clone())]);
+        assert_eq!(product.buffer, ops::MatMul::forward_nograd(rc(a.clone()), rc(b.clone())).buffer);
+        assert_eq!(einsum::einsum("ij,jk", vec![rc(a.clone()), rc(b.clone())]).shape, vec![2, 2]);
+        assert_eq!(einsum::einsum("ij->ji", vec![rc(a.clone())]).values().to_vec(), vec![1.0, 4.0, 2.0, 5.0, 3.0, 6.0]);
+        assert_eq!(einsum::einsum("ij->", vec![rc(a.clone())]).item(), 21.0);
+        assert_eq!(einsum::einsum("ij->j", vec![rc(a.clone())]).values().to_vec(), vec![5.0, 7.0, 9.0]);
+        assert_eq!(einsum::einsum("i,j->ij", vec![rc(tensor![1.0, 2.0]), rc(tensor![3.0, 4.0, 5.0])]).values().to_vec(), vec![3.0, 4.0, 5.0, 6.0, 8.0, 10.0]);
+        assert_eq!(einsum::einsum("ii->i", vec![rc(tensor![[1.0, 2.0], [3.0, 4.0]])]).values().to_vec(), vec![1.0, 4.0]);
+        assert_eq!(einsum::einsum("ii", vec![rc(tensor![[1.0, 2.0], [3.0, 4.0]])]).item(), 5.0);
+        let bilinear = einsum::einsum("i,ij,j->", vec![rc(tensor![1.0, -1.0]), rc(a.clone()), rc(tensor![1.0, 0.0, 2.0])]);
+        assert_eq!(bilinear.item(), (1.0 + 6.0) - (4.0 + 12.0));
+
+        let batched = Tensor::new((0..12).map(|v| v as f32 * 0.25 - 1.0).collect(), &[2, 2, 3]);
+        let right = Tensor::new((0..12).map(|v| 1.0 - v as f32 * 0.2).collect(), &[2, 3, 2]);
+        let out = einsum::einsum("bij,bjk->bik", vec![rc(batched.clone()), rc(right.clone())]);
+        assert_eq!(out.shape, vec![2, 2, 2]);
+        for batch in 0..2 {
+            let expected = ops::MatMul::forward_nograd(rc(batched.slice(0, batch, batch + 1, 1).squeeze(0)), rc(right.slice(0, batch, batch + 1, 1).squeeze(0)));
+            assert_eq!(out.slice(0, batch, batch + 1, 1).squeeze(0).values().to_vec(), expected.values().to_vec());
+        }
+
+        check_gradients(|p| einsum::einsum("ij,jk->ik", p.to_vec()), vec![a.clone(), b.clone()]);
+        check_gradients(|p| einsum::einsum("bij,bjk->bik", p.to_vec()), vec![batched.clone(), right.clone()]);
+        check_gradients(|p| einsum::einsum("bij,bkj->bki", p.to_vec()), vec![batched.clone(), batched.clone()]);
+        check_gradients(|p| einsum::einsum("ij,ij->ij", p.to_vec()), vec![a.clone(), a.clone()]);
+        check_gradients(|p| einsum::einsum("i,ij,j->", p.to_vec()), vec![tensor![0.5, -1.0], a.clone(), tensor![1.0, 0.3, -2.0]]);
+        check_gradients(|p| einsum::einsum("ii->i", p.to_vec()), vec![Tensor::new(vec![0.5, 1.0, -1.0, 2.0], &[2, 2])]);
+
+        let attention: var::Var = var::Var::einsum("qd,kd->qk", &[var::Var::new(a.clone()), var::Var::new(a.clone())]);
+        assert_eq!(attention.to_vec(), vec![14.0, 32.0, 32.0, 77.0]);
+
+        assert!(einsum::try_einsum("ij,jk->ik", vec![rc(a.clone()), rc(a.clone())]).is_err());
+        assert!(einsum::try_einsum("ijk->i", vec![rc(a.clone())]).is_err());
+        assert!(einsum::try_einsum("ij->iz", vec![rc(a.clone())]).is_err());
+        assert!(einsum::try_einsum("ij->ii", vec![rc(a.clone())]).is_err());
+        assert!(einsum::try_einsum("i.j->", vec![rc(a.clone())]).is_err());
+        assert!(matches!(einsum::try_einsum("ij,jk->ik", vec![rc(a)]), Err(error::RsgradError::InvalidArgument {..})));
+    }
+
+    #[test]
+    fn batched_matmul_test() {
+        let rc = |tensor: Tensor| Rc::new(RefCell::new(tensor));
+        let a = Tensor::new((0..12).map(|v| v as f32 * 0.5 - 2.0).collect(), &[2, 2, 3]);
+        let b = Tensor::new((0..12).map(|v| 1.0 - v as f32 * 0.25).collect(), &[2, 3, 2]);
+        let out = ops::MatMul::forward_nograd(rc(a.clone()), rc(b.clone()));
+        assert_eq!(out.shape, vec![2, 2, 2]);
+        for batch in 0..2 {
+            let expected = ops::MatMul::forward_nograd(rc(a.slice(0, batch, batch + 1, 1).squeeze(0)), rc(b.slice(0, batch, batch + 1, 1).squeeze(0)));
+            assert_eq!(out.slice(0, batch, batch + 1, 1).squeeze(0).values().to_vec(), expected.buffer.to_vec());
+        }
+        let shared = b.slice(0, 0, 1, 1).squeeze(0);
+        let broadcast = ops::MatMul::forward_nograd(rc(a.clone()), rc(shared.clone()));
+        assert_eq!(broadcast.slice(0, 1, 2, 1).values().to_vec(), ops::MatMul::forward_nograd(rc(a.slice(0, 1, 2, 1).squeeze(0)), rc(shared.clone())).buffer.to_vec());
+        let stacked = Tensor::new((0..9).map(|v| v as f32).collect(), &[3, 1, 1, 3]);
+        assert_eq!(ops::MatMul::forward_nograd(rc(stacked), rc(b.clone())).shape, vec![3, 2, 1, 2]);
+        assert!(ops::MatMul::try_forward(rc(Tensor::ones(&[2, 2, 3])), rc(Tensor::ones(&[3, 3, 2]))).is_err());
+
+        let matrix: Tensor = tensor![[1.0, 2.0, 3.0], [4.0, 5.0, 6.0]];
+        let vector: Tensor = tensor![1.0, 0.0, -1.0];
+        let matvec = ops::MatMul::forward(rc(matrix.clone()), rc(vector.clone()));
+        assert_eq!((matvec.shape.clone(), matvec.buffer.to_vec()), (vec![2], vec![-2.0, -2.0]));
+        let vecmat = ops::MatMul::forward_nograd(rc(tensor![1.0, -1.0]), rc(matrix.clone()));
+        assert_eq!((vecmat.shape.clone(), vecmat.values().to_vec()), (vec![3], vec![-3.0, -3.0, -3.0]));
+        let dot = ops::MatMul::forward(rc(vector.clone()), rc(vector.clone()));
+        assert_eq!((dot.shape.clone(), dot.item()), (vec![], 2.0));
+        let batched_vector = ops::MatMul::forward_nograd(rc(a.clone()), rc(vector.clone()));
+        assert_eq!(batched_vector.shape, vec![2, 2]);
+
+        check_gradients(|p| ops::MatMul::forward(p[0].clone(), p[1].clone()), vec![a.clone(), b.clone()]);
+        check_gradients(|p| ops::MatMul::forward(p[0].clone(), p[1].clone()), vec![a.clone(), shared]);
+        check_gradients(|p| ops::MatMul::forward(p[0].clone(), p[1].clone()), vec![matrix.clone(), vector.clone()]);
+        check_gradients(|p| ops::MatMul::forward(p[0].clone(), p[1].clone()), vec![tensor![0.5, -1.0], matrix]);
+        check_gradients(|p| ops::MatMul::forward(p[0].clone(), p[1].clone()), vec![a, vector]);
+    }
+
+    #[test]
+    fn gemm_test() {
+        // sizes that leave partial micro rows and partial depth and column blocks
+        let (n, k, m) = (7, 300, 270);
+        let a: Vec<f64> = (0..n*k).map(|v| ((v*7) % 11) as f64 - 5.0).collect();
+        let b: Vec<f64> = (0..k*m).map(|v| ((v*5) % 13) as f64 - 6.0).collect();
+        let mut out = vec![1.0; n*m];
+        gemm::gemm(&a, &b, &mut out, n, k, m);
+        for i in 0..n {
+            for j in 0..m {
+                let expected: f64 = 1.0 + (0..k).map(|p| a[i*k + p]*b[p*m + j]).sum::<f64>();
+                assert_eq!(out[i*m + j], expected);
+            }
+        }
+        let mut empty: Vec<f64> = Vec::new();
+        gemm::gemm(&[], &[], &mut empty, 0, 3, 0);
+        let mut untouched = vec![2.0; 4];
+        gemm::gemm(&[], &[], &mut untouched, 2, 0, 2);
+        assert_eq!(untouched, vec![2.0; 4]);
+    }
+
+    #[cfg(feature = "blas")]
+    #[test]
+    fn blas_gemm_test() {
+        // odd sizes that exercise the leading dimensions and the native kernel's partial blocks
+        let (n, k, m) = (37, 129, 261);
+        let a: Vec<f64> = (0..n*k).map(|v| ((v*7) % 11) as f64/3.0 - 1.5).collect();
+        let b: Vec<f64> = (0..k*m).map(|v| ((v*5) % 13) as f64/4.0 - 1.5).collect();
+        let mut blas_out = vec![0.5; n*m];
+        let mut native_out = blas_out.clone();
+        assert!(blas::gemm(&a, &b, &mut blas_out, n, k, m));
+        gemm::native_gemm(&a, &b, &mut native_out, n, k, m);
+        for (x, y) in blas_out.iter().zip(native_out.iter()) {
+            assert!((x - y).abs() < 1e-9, "{} != {}", x, y);
+        }
+
+        let a: Vec<f32> = a.iter().map(|&v| v as f32).collect();
+        let b: Vec<f32> = b.iter().map(|&v| v as f32).collect();
+        let mut blas_out = vec![0.5f32; n*m];
+        let mut native_out = blas_out.clone();
+        assert!(blas::gemm(&a, &b, &mut blas_out, n, k, m));
+        gemm::native_gemm(&a, &b, &mut native_out, n, k, m);
+        for (x, y) in blas_out.iter().zip(native_out.iter()) {
+            assert!((x - y).abs() < 1e-3, "{} != {}", x, y);
+        }
+
+        // other element types are left to the native kernel
+        let half = vec![half::f16::from_f32(1.0); 4];
+        let mut out = vec![half::f16::from_f32(0.0); 4];
+        assert!(!blas::gemm(&half, &half, &mut out, 2, 2, 2));
+        assert_eq!(out, vec![half::f16::from_f32(0.0); 4]);
+
+        // batched matmul through the dispatching kernel agrees with the native kernel
+        let x = Rc::new(RefCell::new(Tensor::<f32>::new((0..24).map(|v| v as f32/5.0).collect(), &[2, 3, 4])));
+        let y = Rc::new(RefCell::new(Tensor::<f32>::new((0..8).map(|v| 1.0 - v as f32/3.0).collect(), &[4, 2])));
+        let product = ops::MatMul::forward_nograd(x.clone(), y.clone());
+        let xs = x.borrow().values().into_owned();
+        let ys = y.borrow().values().into_owned();
+        for batch in 0..2 {
+            let mut expected = vec![0.0f32; 6];
+            gemm::native_gemm(&xs[batch*12..(batch + 1)*12], &ys, &mut expected, 3, 4, 2);
+            for (got, want) in product.values()[batch*6..(batch + 1)*6].iter().zip(expected.iter()) {
+                assert!((got - want).abs() < 1e-5);
+            }
+        }
+    }
+
 }
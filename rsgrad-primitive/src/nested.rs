@@ -0,0 +1,80 @@
+use crate::element::Element;
+use crate::error::{RsgradError, Result};
+
+/// Nested `Vec`s and arrays of elements that can be read into a tensor with
+/// [`Tensor::from_nested`](crate::tensor::Tensor::from_nested). Every level of nesting adds a
+/// dimension.
+pub trait NestedData {
+    type Element: Element;
+
+    /// The shape read along the first entry of every level.
+    fn nested_shape(&self) -> Vec<u32>;
+
+    /// Appends the elements in row-major order, failing when a level does not have `shape`.
+    fn flatten_into(&self, shape: &[u32], data: &mut Vec<Self::Element>) -> Result<()>;
+}
+
+impl<E: Element> NestedData for E {
+    type Element = E;
+
+    fn nested_shape(&self) -> Vec<u32> {
+        Vec::new()
+    }
+
+    fn flatten_into(&self, shape: &[u32], data: &mut Vec<E>) -> Result<()> {
+        if !shape.is_empty() {
+            return Err(RsgradError::shape("from_nested", &[], shape))
+        }
+        data.push(*self);
+        Ok(())
+    }
+}
+
+fn slice_shape<N: NestedData>(items: &[N]) -> Vec<u32> {
+    let mut shape = vec![items.len() as u32];
+    if let Some(first) = items.first() {
+        shape.extend(first.nested_shape());
+    }
+    shape
+}
+
+fn flatten_slice<N: NestedData>(items: &[N], shape: &[u32], data: &mut Vec<N::Element>) -> Result<()> {
+    if shape.first() != Some(&(items.len() as u32)) {
+        return Err(RsgradError::shape("from_nested", &slice_shape(items), shape))
+    }
+    items.iter().try_for_each(|item| item.flatten_into(&shape[1..], data))
+}
+
+impl<N: NestedData> NestedData for Vec<N> {
+    type Element = N::Element;
+
+    fn nested_shape(&self) -> Vec<u32> {
+        slice_shape(self)
+    }
+
+    fn flatten_into(&self, shape: &[u32], data: &mut Vec<N::Element>) -> Result<()> {
+        flatten_slice(self, shape, data)
+    }
+}
+
+impl<N: NestedData, const K: usize> NestedData for [N; K] {
+    type Element = N::Element;
+
+    fn nested_shape(&self) -> Vec<u32> {
+        slice_shape(self)
+    }
+
+    fn flatten_into(&self, shape: &[u32], data: &mut Vec<N::Element>) -> Result<()> {
+        flatten_slice(self, shape, data)
+    }
+}
+
+/// Builds a tensor from a literal, one level of brackets per dimension:
+/// `tensor![[1.0, 2.0], [3.0, 4.0]]` is a 2 by 2 matrix. The element type follows the
+/// literals, so `tensor![1, 2, 3]` holds `i32` unless annotated. Panics on ragged input.
+#[macro_export]
+macro_rules! tensor {
+    ($($item:expr),+ $(,)?) => {
+        $crate::tensor::Tensor::from_nested([$($item),+])
+    };
+}
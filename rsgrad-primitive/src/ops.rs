@@ -1,46 +1,150 @@
-use crate::tensor::Tensor;
+use crate::tensor::{Tensor, broadcast_shape, reduced_shape, unravel, numel, lanes, beats, contiguous_stride, try_broadcast_shape, try_broadcast_stride, try_reduced_shape};
+use crate::error::{RsgradError, Result, or_panic, check_index};
+use crate::grad_mode::GradModeGuard;
+use crate::element::{Element, Float};
+use crate::gemm::gemm;
 use std::rc::Rc;
 use std::cell::RefCell;
 
+/// A differentiable operation. Implementing it is all that is needed to add an op outside of
+/// this crate: run it with [`apply`] and the result takes part in backward and forward mode
+/// like any builtin op.
+pub trait Function<T: Element = f32> {
+    /// Short name used when inspecting a graph.
+    fn name(&self) -> &str;
+
+    /// Computes the result from the inputs. Recording the inputs is left to [`apply`].
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T>;
+
+    /// Returns the vector-Jacobian product for every input given the `grad` of the output.
+    /// Building these out of recording ops (`Mult::forward` rather than
+    /// `Mult::forward_nograd`) is what lets `create_graph` differentiate them again.
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>>;
+
+    /// Validates the inputs before [`Function::forward`] runs. [`try_apply`] turns a failed
+    /// check into an error, [`apply`] into a panic.
+    fn check(&self, _x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Pushes the input `tangents` through the op, `out` is the already computed result.
+    /// Without a dedicated rule the product is recovered by differentiating `<vjp(u), tangents>`
+    /// with respect to `u`, which costs an extra small backward pass.
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T>
+    where T: Float {
+        let _mode = GradModeGuard::new(true);
+        let inputs: Vec<Rc<RefCell<Tensor<T>>>> = x.iter().map(|input| rc(input.borrow().detach())).collect();
+        let u = rc(Tensor::constant_fill(T::zero(), &out.shape()));
+        let mut total: Option<Rc<RefCell<Tensor<T>>>> = None;
+        for (grad, tangent) in self.vjp(u.clone(), &inputs).into_iter().zip(tangents) {
+            let dot = rc(Sum::forward(rc(Mult::forward(rc(grad), rc(tangent.borrow().detach())))));
+            total = Some(match total {
+                Some(previous) => rc(Add::forward(previous, dot)),
+                None => dot
+            });
+        }
+        if let Some(total) = total {
+            total.borrow_mut().backward(constant(1.0));
+        }
+        let result = match u.borrow().grad.as_ref() {
+            Some(grad) => grad.borrow().detach(),
+            None => Tensor::constant_fill(T::zero(), &out.shape())
+        };
+        result
+    }
+}
+
+/// Runs `op` on `x` and records it in the graph, subject to grad mode and `requires_grad`.
+pub fn apply<T: Float>(op: Rc<dyn Function<T>>, x: Vec<Rc<RefCell<Tensor<T>>>>) -> Tensor<T> {
+    or_panic(try_apply(op, x))
+}
+
+/// Like [`apply`], but returns an error when the inputs do not pass the op's check.
+pub fn try_apply<T: Float>(op: Rc<dyn Function<T>>, x: Vec<Rc<RefCell<Tensor<T>>>>) -> Result<Tensor<T>> {
+    op.check(&x)?;
+    Ok(op.forward(&x).record(op, x))
+}
+
+fn map<T: Float>(a: &Tensor<T>, f: impl Fn(T) -> T) -> Tensor<T> {
+    Tensor::new(a.values().iter().map(|&x| f(x)).collect(), &a.shape)
+}
+
+/// Combines `a` and `b` elementwise after broadcasting them to a common shape.
+fn zip_map<T: Float>(a: &Tensor<T>, b: &Tensor<T>, f: impl Fn(T, T) -> T) -> Tensor<T> {
+    if a.shape == b.shape {
+        return Tensor::new(a.values().iter().zip(b.values().iter()).map(|(&x, &y)| f(x, y)).collect(), &a.shape)
+    }
+    let shape = broadcast_shape(&a.shape, &b.shape);
+    let a_offsets = a.broadcast_offsets(&shape);
+    let b_offsets = b.broadcast_offsets(&shape);
+    let buffer: Vec<T> = a_offsets.iter().zip(b_offsets.iter()).map(|(&i, &j)| f(a.buffer[i], b.buffer[j])).collect();
+    Tensor::new(buffer, &shape)
+}
+
+/// Reads an index tensor for `op`, whose values have to be non-negative.
+fn indices(op: &str, index: &Tensor<i64>) -> Vec<u32> {
+    or_panic(try_indices(op, index))
+}
+
+fn try_indices(op: &str, index: &Tensor<i64>) -> Result<Vec<u32>> {
+    index.values().iter().map(|&value| {
+        u32::try_from(value).map_err(|_| RsgradError::invalid(op, &format!("negative index {}", value)))
+    }).collect()
+}
+
+fn check_broadcast<T: Float>(x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+    try_broadcast_shape(&x[0].borrow().shape, &x[1].borrow().shape).map(|_| ())
+}
+
+fn check_indices(index: &[u32], size: u32) -> Result<()> {
+    index.iter().try_for_each(|&i| check_index(i as usize, size as usize))
+}
+
+fn rc<T: Float>(tensor: Tensor<T>) -> Rc<RefCell<Tensor<T>>> {
+    Rc::new(RefCell::new(tensor))
+}
+
+/// A 0-d constant, as used in the vjps.
+fn constant<T: Float>(value: f64) -> Rc<RefCell<Tensor<T>>> {
+    rc(Tensor::scalar(T::from_f64(value)))
+}
+
 #[derive(Clone)]
 pub struct Add;
 
 impl Add {
-    pub fn forward_nograd(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = a_tensor.buffer[i]+b_tensor.buffer[i];
-        }
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: Vec::new(), op: None}
-    }
-
-    pub fn forward(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = a_tensor.buffer[i]+b_tensor.buffer[i];
-        }
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        children.push(b.clone());
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: children, op: Some(Op::ADD)}
-    }
-
-    pub fn vjp(grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        let mut result: Vec<Tensor> = Vec::new();
-        let mut ones_tensor = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &(x[0].borrow().shape())[..])));
-        let mut ones_tensor_dup = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &(x[0].borrow().shape())[..])));
-
-        let mut ones_tensor_rc = Mult::forward(grad.clone(), ones_tensor);
-        let mut ones_tensor_rc_dup = Mult::forward(grad.clone(), ones_tensor_dup);
-        result.push(ones_tensor_rc);
-        result.push(ones_tensor_rc_dup);
-        result
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Add.forward(&[a, b])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Add), vec![a, b])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Add), vec![a, b])
+    }
+}
+
+impl<T: Float> Function<T> for Add {
+    fn name(&self) -> &str {
+        "Add"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_broadcast(x)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        zip_map(&x[0].borrow(), &x[1].borrow(), |a, b| a+b)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![SumTo::forward(grad.clone(), &x[0].borrow().shape), SumTo::forward(grad, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Add::forward_nograd(tangents[0].clone(), tangents[1].clone())
     }
 }
 
@@ -48,41 +152,39 @@ impl Add {
 pub struct Sub;
 
 impl Sub {
-    pub fn forward_nograd(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = a_tensor.buffer[i]+b_tensor.buffer[i];
-        }
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: Vec::new(), op: None}
-    }
-
-    pub fn forward(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = a_tensor.buffer[i]-b_tensor.buffer[i];
-        }
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        children.push(b.clone());
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: children, op: Some(Op::SUB)}
-    }
-
-    pub fn vjp(grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        let mut result: Vec<Tensor> = Vec::new();
-        let mut ones_tensor = Rc::new(RefCell::new(Tensor::constant_fill(1.0, &(x[0].borrow().shape())[..])));
-        let mut ones_tensor_dup = Rc::new(RefCell::new(Tensor::constant_fill(-1.0, &(x[0].borrow().shape())[..])));
-
-        let mut ones_tensor_rc = Mult::forward(grad.clone(), ones_tensor);
-        let mut ones_tensor_rc_dup = Mult::forward(grad.clone(), ones_tensor_dup);
-        result.push(ones_tensor_rc);
-        result.push(ones_tensor_rc_dup);
-        result
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Sub.forward(&[a, b])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Sub), vec![a, b])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Sub), vec![a, b])
+    }
+}
+
+impl<T: Float> Function<T> for Sub {
+    fn name(&self) -> &str {
+        "Sub"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_broadcast(x)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        zip_map(&x[0].borrow(), &x[1].borrow(), |a, b| a-b)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let negated = rc(Mult::forward(grad.clone(), constant(-1.0)));
+        vec![SumTo::forward(grad, &x[0].borrow().shape), SumTo::forward(negated, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Sub::forward_nograd(tangents[0].clone(), tangents[1].clone())
     }
 }
 
@@ -90,37 +192,42 @@ impl Sub {
 pub struct Mult;
 
 impl Mult {
-    pub fn forward_nograd(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = a_tensor.buffer[i]*b_tensor.buffer[i];
-        }
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: Vec::new(), op: None}
-    }
-
-    pub fn forward(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = a_tensor.buffer[i]*b_tensor.buffer[i];
-        }
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        children.push(b.clone());
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: children, op: Some(Op::MULT)}
-    }
-
-    pub fn vjp(grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        let mut result: Vec<Tensor> = Vec::new();
-        result.push(Mult::forward_nograd(grad.clone(), x[1].clone()));
-        result.push(Mult::forward_nograd(grad.clone(), x[0].clone()));
-        //result.push(x[0].borrow().clone());
-        result
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Mult.forward(&[a, b])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Mult), vec![a, b])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Mult), vec![a, b])
+    }
+}
+
+impl<T: Float> Function<T> for Mult {
+    fn name(&self) -> &str {
+        "Mult"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_broadcast(x)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        zip_map(&x[0].borrow(), &x[1].borrow(), |a, b| a*b)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let grad_a = rc(Mult::forward(grad.clone(), x[1].clone()));
+        let grad_b = rc(Mult::forward(grad, x[0].clone()));
+        vec![SumTo::forward(grad_a, &x[0].borrow().shape), SumTo::forward(grad_b, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let left = rc(Mult::forward_nograd(tangents[0].clone(), x[1].clone()));
+        let right = rc(Mult::forward_nograd(x[0].clone(), tangents[1].clone()));
+        Add::forward_nograd(left, right)
     }
 }
 
@@ -128,108 +235,135 @@ impl Mult {
 pub struct Log;
 
 impl Log {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Log.forward(&[a])
+    }
 
-    pub fn forward(a: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = a_tensor.buffer[i].ln();
-        }
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: children, op: Some(Op::LOG)}
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Log), vec![a])
     }
 
-    pub fn vjp(grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        let mut result: Vec<Tensor> = Vec::new();
-        let a_tensor = x[0].borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = 1.0/a_tensor.buffer[i];
-        }
-        let res_tensor = Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: Vec::new(), op: None};
-        result.push(Mult::forward_nograd(grad, Rc::new(RefCell::new(res_tensor))));
-        result
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Log), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Log {
+    fn name(&self) -> &str {
+        "Log"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::ln)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Div::forward(grad, x[0].clone())]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Div::forward_nograd(tangents[0].clone(), x[0].clone())
     }
 }
 
+/// Matrix product following NumPy's `matmul`: the last two dimensions are multiplied as
+/// matrices and any leading dimensions are batch dimensions, broadcast between `a` and `b`. A
+/// 1-d `a` is a row vector and a 1-d `b` a column vector, whose dimension is dropped again from
+/// the result, so matrix-vector and vector-matrix products work as well.
 #[derive(Clone)]
 pub struct MatMul;
 
 impl MatMul {
-
-
-    pub fn forward_nograd(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        assert_eq!(a_tensor.stride.len(), 2);
-        assert_eq!(b_tensor.stride.len(), 2);
-
-        let a_shape = a_tensor.shape();
-        let b_shape = b_tensor.shape();
-        assert_eq!(a_shape[1], b_shape[0]);
-        let bufsize = a_shape[0]*b_shape[1];
-        let m = a_shape[1];
-        let mut buffer: Vec<f32> = vec![1.0; usize::try_from(bufsize).unwrap()];
-        let mut result = Tensor::new(buffer, &[a_shape[0], b_shape[1]]);
-        for i in 0..a_shape[0] {
-            for j in 0..b_shape[1] {
-                let mut current: f32 = 0.0;
-                for k in 0..m {
-                    current += (*a_tensor.at_im(&[i, k])) * (*b_tensor.at_im(&[k, j]));
-                }
-                *result.at(&[i, j]) = current;
-            }
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        let (a_vector, b_vector) = (a.borrow().ndim() == 1, b.borrow().ndim() == 1);
+        let a = if a_vector {rc(a.borrow().unsqueeze(0))} else {a};
+        let b = if b_vector {rc(b.borrow().unsqueeze(1))} else {b};
+        let mut result = MatMul.forward(&[a, b]);
+        if b_vector {
+            result = result.squeeze(result.ndim() - 1);
+        }
+        if a_vector {
+            result = result.squeeze(result.ndim() - 1 - (!b_vector) as usize);
         }
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        children.push(b.clone());
-        result.children = children;
-        result.op = Some(Op::MATMUL);
         result
     }
 
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        or_panic(MatMul::try_forward(a, b))
+    }
 
-    pub fn forward(a: Rc<RefCell<Tensor>>, b: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let b_tensor = b.borrow();
-        assert_eq!(a_tensor.stride.len(), 2);
-        assert_eq!(b_tensor.stride.len(), 2);
-
-        let a_shape = a_tensor.shape();
-        let b_shape = b_tensor.shape();
-        let bufsize = a_shape[0]*b_shape[1];
-        let m = a_shape[1];
-        let mut buffer: Vec<f32> = vec![1.0; usize::try_from(bufsize).unwrap()];
-        let mut result = Tensor::new(buffer, &[a_shape[0], b_shape[1]]);
-        for i in 0..a_shape[0] {
-            for j in 0..b_shape[1] {
-                let mut current: f32 = 0.0;
-                for k in 0..m {
-                    current += (*a_tensor.at_im(&[i, k])) * (*b_tensor.at_im(&[k, j]));
-                }
-                *result.at(&[i, j]) = current;
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        let (a_vector, b_vector) = (a.borrow().ndim() == 1, b.borrow().ndim() == 1);
+        let a = if a_vector {rc(Unsqueeze::try_forward(a, 0)?)} else {a};
+        let b = if b_vector {rc(Unsqueeze::try_forward(b, 1)?)} else {b};
+        let mut result = try_apply(Rc::new(MatMul), vec![a, b])?;
+        if b_vector {
+            let dim = result.ndim() - 1;
+            result = Squeeze::try_forward(rc(result), dim)?;
+        }
+        if a_vector {
+            let dim = result.ndim() - 1 - (!b_vector) as usize;
+            result = Squeeze::try_forward(rc(result), dim)?;
+        }
+        Ok(result)
+    }
+
+    /// The shape of the product, batch dimensions first.
+    fn try_shape(a: &[u32], b: &[u32]) -> Result<Vec<u32>> {
+        for shape in [a, b] {
+            if shape.len() < 2 {
+                return Err(RsgradError::rank("MatMul", 2, shape.len()))
             }
         }
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        children.push(b.clone());
-        result.children = children;
-        result.op = Some(Op::MATMUL);
-        result
+        let (a_batch, b_batch) = (&a[..a.len()-2], &b[..b.len()-2]);
+        if a[a.len()-1] != b[b.len()-2] {
+            return Err(RsgradError::shape("MatMul", a, b))
+        }
+        let mut shape = try_broadcast_shape(a_batch, b_batch).map_err(|_| RsgradError::shape("MatMul", a, b))?;
+        shape.extend([a[a.len()-2], b[b.len()-1]]);
+        Ok(shape)
     }
+}
 
-    pub fn vjp(grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        let mut result: Vec<Tensor> = Vec::new();
+impl<T: Float> Function<T> for MatMul {
+    fn name(&self) -> &str {
+        "MatMul"
+    }
 
-        let a_transpose = Rc::new(RefCell::new(x[0].borrow().transpose()));
-        let b_transpose = Rc::new(RefCell::new(x[1].borrow().transpose()));
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        MatMul::try_shape(&x[0].borrow().shape, &x[1].borrow().shape).map(|_| ())
+    }
 
-        result.push(MatMul::forward_nograd(grad.clone(), b_transpose));
-        result.push(MatMul::forward_nograd(a_transpose, grad.clone()));
-        result
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let (a, b) = (x[0].borrow(), x[1].borrow());
+        let shape = or_panic(MatMul::try_shape(&a.shape, &b.shape));
+        let batch = &shape[..shape.len()-2];
+        let (n, k, m) = (a.shape[a.ndim()-2] as usize, a.shape[a.ndim()-1] as usize, b.shape[b.ndim()-1] as usize);
+        // broadcasting the batch dimensions up front leaves one contiguous matrix per batch entry
+        let a_shape: Vec<u32> = batch.iter().copied().chain([n as u32, k as u32]).collect();
+        let b_shape: Vec<u32> = batch.iter().copied().chain([k as u32, m as u32]).collect();
+        let (a_expanded, b_expanded) = (a.expand(&a_shape), b.expand(&b_shape));
+        let (a_values, b_values) = (a_expanded.values(), b_expanded.values());
+        let mut buffer: Vec<T> = vec![T::zero(); numel(&shape)];
+        for index in 0..numel(batch) {
+            gemm(&a_values[index*n*k..(index+1)*n*k], &b_values[index*k*m..(index+1)*k*m], &mut buffer[index*n*m..(index+1)*n*m], n, k, m);
+        }
+        Tensor::new(buffer, &shape)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let a_transpose = rc(Transpose::forward(x[0].clone()));
+        let b_transpose = rc(Transpose::forward(x[1].clone()));
+        // batch dimensions that were broadcast are summed back down to the shape of each input
+        let grad_a = rc(MatMul::forward(grad.clone(), b_transpose));
+        let grad_b = rc(MatMul::forward(a_transpose, grad));
+        vec![SumTo::forward(grad_a, &x[0].borrow().shape), SumTo::forward(grad_b, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let left = rc(MatMul::forward_nograd(tangents[0].clone(), x[1].clone()));
+        let right = rc(MatMul::forward_nograd(x[0].clone(), tangents[1].clone()));
+        Add::forward_nograd(left, right)
     }
 }
 
@@ -237,30 +371,40 @@ impl MatMul {
 pub struct Relu;
 
 impl Relu {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Relu.forward(&[a])
+    }
 
-    pub fn forward(a: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = if a_tensor.buffer[i]>0.0 {a_tensor.buffer[i]} else {0.0};
-        }
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: children, op: Some(Op::RELU)}
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Relu), vec![a])
     }
 
-    pub fn vjp(grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        let mut result: Vec<Tensor> = Vec::new();
-        let a_tensor = x[0].borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = if a_tensor.buffer[i]>0.0 {1.0} else {0.0};
-        }
-        let res_tensor = Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: Vec::new(), op: None};
-        result.push(Mult::forward_nograd(grad, Rc::new(RefCell::new(res_tensor))));
-        result
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Relu), vec![a])
+    }
+
+    fn mask<T: Float>(a: &Tensor<T>) -> Tensor<T> {
+        map(a, |x| if x>T::zero() {T::one()} else {T::zero()})
+    }
+}
+
+impl<T: Float> Function<T> for Relu {
+    fn name(&self) -> &str {
+        "Relu"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), |x| if x>T::zero() {x} else {T::zero()})
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let mask = rc(Relu::mask(&x[0].borrow()));
+        vec![Mult::forward(grad, mask)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let mask = rc(Relu::mask(&x[0].borrow()));
+        Mult::forward_nograd(tangents[0].clone(), mask)
     }
 }
 
@@ -268,54 +412,2352 @@ impl Relu {
 pub struct L2norm;
 
 impl L2norm {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        L2norm.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(L2norm), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(L2norm), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for L2norm {
+    fn name(&self) -> &str {
+        "L2norm"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        Tensor::scalar(x[0].borrow().values().iter().fold(T::zero(), |acc, &x| acc + x*x))
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let scaled = rc(Mult::forward(x[0].clone(), constant(2.0)));
+        vec![Mult::forward(grad, scaled)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let a_tensor = x[0].borrow();
+        let tangent = tangents[0].borrow();
+        let two = T::from_f64(2.0);
+        Tensor::scalar(a_tensor.values().iter().zip(tangent.values().iter()).fold(T::zero(), |acc, (&a, &t)| acc + two*a*t))
+    }
+}
+
+#[derive(Clone)]
+pub struct Div;
+
+impl Div {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Div.forward(&[a, b])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Div), vec![a, b])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Div), vec![a, b])
+    }
+}
+
+impl<T: Float> Function<T> for Div {
+    fn name(&self) -> &str {
+        "Div"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_broadcast(x)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        zip_map(&x[0].borrow(), &x[1].borrow(), |a, b| a/b)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let grad_a = rc(Div::forward(grad.clone(), x[1].clone()));
+        // d(a/b)/db = -a/b^2
+        let numerator = rc(Mult::forward(grad, x[0].clone()));
+        let denominator = rc(Mult::forward(x[1].clone(), x[1].clone()));
+        let quotient = rc(Div::forward(numerator, denominator));
+        let grad_b = rc(Mult::forward(quotient, constant(-1.0)));
+        vec![SumTo::forward(grad_a, &x[0].borrow().shape), SumTo::forward(grad_b, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        // (ta - out*tb)/b
+        let out_tb = rc(zip_map(out, &tangents[1].borrow(), |o, t| o*t));
+        let numerator = rc(Sub::forward_nograd(tangents[0].clone(), out_tb));
+        Div::forward_nograd(numerator, x[1].clone())
+    }
+}
+
+/// Swaps the last two dimensions, as a view.
+#[derive(Clone)]
+pub struct Transpose;
+
+impl Transpose {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Transpose.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Transpose), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Transpose), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Transpose {
+    fn name(&self) -> &str {
+        "Transpose"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        x[0].borrow().try_transpose().map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        x[0].borrow().transpose()
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Transpose::forward(grad)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Transpose::forward_nograd(tangents[0].clone())
+    }
+}
+
+/// Sums every element into a 0-d tensor.
+#[derive(Clone)]
+pub struct Sum;
+
+impl Sum {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Sum.forward(&[a])
+    }
 
-    pub fn forward(a: Rc<RefCell<Tensor>>)-> Tensor {
-        let a_tensor = a.borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; 1];
-        buffer[0] = a_tensor.buffer.iter().fold(0.0, |acc, &x| acc + x*x);
-        let mut children: Vec<Rc<RefCell<Tensor>>> = Vec::new();
-        children.push(a.clone());
-        Tensor{buffer: buffer, stride: vec![1; 1], grad: None, children: children, op: Some(Op::L2NORM)}
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Sum), vec![a])
     }
 
-    pub fn vjp(grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        let mut result: Vec<Tensor> = Vec::new();
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Sum), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Sum {
+    fn name(&self) -> &str {
+        "Sum"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        Tensor::scalar(x[0].borrow().values().iter().fold(T::zero(), |acc, &x| acc + x))
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Expand::forward(grad, &x[0].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Sum::forward_nograd(tangents[0].clone())
+    }
+}
+
+/// Broadcasts the input to `shape` following the same rules as the elementwise ops. The
+/// result is a view with zero strides along the repeated dimensions.
+#[derive(Clone)]
+pub struct Expand {
+    pub shape: Vec<u32>
+}
+
+impl Expand {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Tensor<T> {
+        Expand {shape: shape.to_vec()}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Tensor<T> {
+        apply(Rc::new(Expand {shape: shape.to_vec()}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Result<Tensor<T>> {
+        try_apply(Rc::new(Expand {shape: shape.to_vec()}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Expand {
+    fn name(&self) -> &str {
+        "Expand"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let a = x[0].borrow();
+        try_broadcast_stride(&a.shape, &a.stride, &self.shape).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        x[0].borrow().expand(&self.shape)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![SumTo::forward(grad, &x[0].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Expand::forward_nograd(tangents[0].clone(), &self.shape)
+    }
+}
+
+/// Sums the input over the dimensions that broadcasting to it would have repeated, so that
+/// the result has `shape`. This is how grads of broadcast inputs get back to their own shape.
+#[derive(Clone)]
+pub struct SumTo {
+    pub shape: Vec<u32>
+}
+
+impl SumTo {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Tensor<T> {
+        SumTo {shape: shape.to_vec()}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Tensor<T> {
+        apply(Rc::new(SumTo {shape: shape.to_vec()}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Result<Tensor<T>> {
+        try_apply(Rc::new(SumTo {shape: shape.to_vec()}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for SumTo {
+    fn name(&self) -> &str {
+        "SumTo"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        try_broadcast_stride(&self.shape, &contiguous_stride(&self.shape), &x[0].borrow().shape).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
         let a_tensor = x[0].borrow();
-        let bufsize = a_tensor.buffer.len();
-        let mut buffer: Vec<f32> = vec![1.0; bufsize];
-        for i in 0..bufsize {
-            buffer[i] = 2.0*a_tensor.buffer[i];
-        }
-        let res_tensor = Tensor{buffer: buffer, stride: a_tensor.stride.clone(), grad: None, children: Vec::new(), op: None};
-        let grad_broadcast = Rc::new(RefCell::new(Tensor::constant_fill(grad.borrow().buffer[0], &a_tensor.shape())));
-        result.push(Mult::forward_nograd(grad_broadcast, Rc::new(RefCell::new(res_tensor))));
+        if a_tensor.shape == self.shape {
+            return a_tensor.contiguous()
+        }
+        let mut result = Tensor::constant_fill(T::zero(), &self.shape);
+        let targets = result.broadcast_offsets(&a_tensor.shape);
+        for (target, value) in targets.into_iter().zip(a_tensor.values().iter()) {
+            result.buffer[target] += *value;
+        }
         result
     }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Expand::forward(grad, &x[0].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        SumTo::forward_nograd(tangents[0].clone(), &self.shape)
+    }
 }
 
+/// Same elements under a new shape, a view whenever the input is contiguous.
 #[derive(Clone)]
-pub enum Op {
-    ADD,
-    SUB,
-    MULT,
-    LOG,
-    MATMUL,
-    RELU,
-    L2NORM
+pub struct Reshape {
+    pub shape: Vec<u32>
+}
+
+impl Reshape {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Tensor<T> {
+        Reshape {shape: shape.to_vec()}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Tensor<T> {
+        apply(Rc::new(Reshape {shape: shape.to_vec()}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32])-> Result<Tensor<T>> {
+        try_apply(Rc::new(Reshape {shape: shape.to_vec()}), vec![a])
+    }
 }
 
-impl Op {
+impl<T: Float> Function<T> for Reshape {
+    fn name(&self) -> &str {
+        "Reshape"
+    }
 
-    pub fn fetch_vjp(&self, grad: Rc<RefCell<Tensor>>, x: &Vec<Rc<RefCell<Tensor>>>) -> Vec<Tensor> {
-        match self {
-            Op::ADD => Add::vjp(grad, x),
-            Op::SUB => Sub::vjp(grad, x),
-            Op::MULT => Mult::vjp(grad, x),
-            Op::LOG => Log::vjp(grad, x),
-            Op::MATMUL => MatMul::vjp(grad, x),
-            Op::RELU => Relu::vjp(grad, x),
-            Op::L2NORM => L2norm::vjp(grad, x)
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let a = x[0].borrow();
+        if a.numel() != numel(&self.shape) {
+            return Err(RsgradError::shape("Reshape", &a.shape, &self.shape))
         }
+        Ok(())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        x[0].borrow().reshape(&self.shape)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Reshape::forward(grad, &x[0].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Reshape::forward_nograd(tangents[0].clone(), &self.shape)
+    }
+}
+
+/// Reorders the dimensions as a view, dimension `i` of the result is dimension `dims[i]` of
+/// the input.
+#[derive(Clone)]
+pub struct Permute {
+    pub dims: Vec<usize>
+}
+
+impl Permute {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize])-> Tensor<T> {
+        Permute {dims: dims.to_vec()}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize])-> Tensor<T> {
+        apply(Rc::new(Permute {dims: dims.to_vec()}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize])-> Result<Tensor<T>> {
+        try_apply(Rc::new(Permute {dims: dims.to_vec()}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Permute {
+    fn name(&self) -> &str {
+        "Permute"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        x[0].borrow().try_permute(&self.dims).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        x[0].borrow().permute(&self.dims)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let mut inverse: Vec<usize> = vec![0; self.dims.len()];
+        for (idx, &dim) in self.dims.iter().enumerate() {
+            inverse[dim] = idx;
+        }
+        vec![Permute::forward(grad, &inverse)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Permute::forward_nograd(tangents[0].clone(), &self.dims)
+    }
+}
+
+/// Removes a dimension of size 1, as a view.
+#[derive(Clone)]
+pub struct Squeeze {
+    pub dim: usize
+}
+
+impl Squeeze {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        Squeeze {dim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        apply(Rc::new(Squeeze {dim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Squeeze {dim}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Squeeze {
+    fn name(&self) -> &str {
+        "Squeeze"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        x[0].borrow().try_squeeze(self.dim).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        x[0].borrow().squeeze(self.dim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Unsqueeze::forward(grad, self.dim)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Squeeze::forward_nograd(tangents[0].clone(), self.dim)
+    }
+}
+
+/// Inserts a dimension of size 1, as a view.
+#[derive(Clone)]
+pub struct Unsqueeze {
+    pub dim: usize
+}
+
+impl Unsqueeze {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        Unsqueeze {dim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        apply(Rc::new(Unsqueeze {dim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Unsqueeze {dim}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Unsqueeze {
+    fn name(&self) -> &str {
+        "Unsqueeze"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        x[0].borrow().try_unsqueeze(self.dim).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        x[0].borrow().unsqueeze(self.dim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Squeeze::forward(grad, self.dim)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Unsqueeze::forward_nograd(tangents[0].clone(), self.dim)
+    }
+}
+
+/// Every `step`-th element along `dim` from `start` up to, not including, `end`, as a view.
+#[derive(Clone)]
+pub struct Slice {
+    pub dim: usize,
+    pub start: u32,
+    pub end: u32,
+    pub step: u32
+}
+
+impl Slice {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, start: u32, end: u32, step: u32)-> Tensor<T> {
+        Slice {dim, start, end, step}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, start: u32, end: u32, step: u32)-> Tensor<T> {
+        apply(Rc::new(Slice {dim, start, end, step}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, start: u32, end: u32, step: u32)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Slice {dim, start, end, step}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Slice {
+    fn name(&self) -> &str {
+        "Slice"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        x[0].borrow().try_slice(self.dim, self.start, self.end, self.step).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        x[0].borrow().slice(self.dim, self.start, self.end, self.step)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![SliceScatter::forward(grad, &x[0].borrow().shape, self.dim, self.start, self.end, self.step)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Slice::forward_nograd(tangents[0].clone(), self.dim, self.start, self.end, self.step)
+    }
+}
+
+/// Writes the input into the given slice of a zero tensor of `shape`, the adjoint of [`Slice`].
+#[derive(Clone)]
+pub struct SliceScatter {
+    pub shape: Vec<u32>,
+    pub slice: Slice
+}
+
+impl SliceScatter {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32], dim: usize, start: u32, end: u32, step: u32)-> Tensor<T> {
+        SliceScatter {shape: shape.to_vec(), slice: Slice {dim, start, end, step}}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32], dim: usize, start: u32, end: u32, step: u32)-> Tensor<T> {
+        apply(Rc::new(SliceScatter {shape: shape.to_vec(), slice: Slice {dim, start, end, step}}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, shape: &[u32], dim: usize, start: u32, end: u32, step: u32)-> Result<Tensor<T>> {
+        try_apply(Rc::new(SliceScatter {shape: shape.to_vec(), slice: Slice {dim, start, end, step}}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for SliceScatter {
+    fn name(&self) -> &str {
+        "SliceScatter"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        // an expanded scalar has the layout of the result without allocating it
+        let zeros = Tensor::constant_fill(T::zero(), &[]).try_expand(&self.shape)?;
+        let target = zeros.try_slice(self.slice.dim, self.slice.start, self.slice.end, self.slice.step)?;
+        let src = x[0].borrow();
+        if src.shape != target.shape {
+            return Err(RsgradError::shape("SliceScatter", &src.shape, &target.shape))
+        }
+        Ok(())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let src = x[0].borrow();
+        let mut result = Tensor::constant_fill(T::zero(), &self.shape);
+        let target = result.slice(self.slice.dim, self.slice.start, self.slice.end, self.slice.step);
+        assert_eq!(src.shape, target.shape, "cannot scatter {:?} into a slice of shape {:?}", src.shape, target.shape);
+        let offsets = target.offsets();
+        drop(target);
+        for (offset, &value) in offsets.into_iter().zip(src.values().iter()) {
+            result.buffer[offset] = value;
+        }
+        result
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Slice::forward(grad, self.slice.dim, self.slice.start, self.slice.end, self.slice.step)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// Picks the entries `index` along `dim`, in that order and possibly repeated.
+#[derive(Clone)]
+pub struct IndexSelect {
+    pub dim: usize,
+    pub index: Vec<u32>
+}
+
+impl IndexSelect {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &[u32])-> Tensor<T> {
+        IndexSelect {dim, index: index.to_vec()}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &[u32])-> Tensor<T> {
+        apply(Rc::new(IndexSelect {dim, index: index.to_vec()}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &[u32])-> Result<Tensor<T>> {
+        try_apply(Rc::new(IndexSelect {dim, index: index.to_vec()}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for IndexSelect {
+    fn name(&self) -> &str {
+        "IndexSelect"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let a = x[0].borrow();
+        check_index(self.dim, a.ndim())?;
+        check_indices(&self.index, a.shape[self.dim])
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        assert!(self.dim < a.ndim(), "cannot index dimension {} of {:?}", self.dim, a.shape);
+        let mut shape = a.shape.clone();
+        shape[self.dim] = self.index.len() as u32;
+        let mut result = Tensor::constant_fill(T::zero(), &shape);
+        for (flat, value) in result.buffer.iter_mut().enumerate() {
+            let mut position = unravel(flat, &shape);
+            position[self.dim] = self.index[position[self.dim] as usize];
+            *value = *a.at_im(&position);
+        }
+        result
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let zeros = rc(Tensor::constant_fill(T::zero(), &x[0].borrow().shape).with_requires_grad(false));
+        vec![IndexAdd::forward(zeros, self.dim, &self.index, grad)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        IndexSelect::forward_nograd(tangents[0].clone(), self.dim, &self.index)
+    }
+}
+
+/// Adds the entries of `src` along `dim` into `a` at the positions `index`, repeated positions
+/// accumulate. The adjoint of [`IndexSelect`].
+#[derive(Clone)]
+pub struct IndexAdd {
+    pub dim: usize,
+    pub index: Vec<u32>
+}
+
+impl IndexAdd {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &[u32], src: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        IndexAdd {dim, index: index.to_vec()}.forward(&[a, src])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &[u32], src: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(IndexAdd {dim, index: index.to_vec()}), vec![a, src])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &[u32], src: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(IndexAdd {dim, index: index.to_vec()}), vec![a, src])
+    }
+}
+
+impl<T: Float> Function<T> for IndexAdd {
+    fn name(&self) -> &str {
+        "IndexAdd"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let (a, src) = (x[0].borrow(), x[1].borrow());
+        check_index(self.dim, a.ndim())?;
+        if src.ndim() != a.ndim() {
+            return Err(RsgradError::rank("IndexAdd", a.ndim(), src.ndim()))
+        }
+        let mut expected = a.shape.clone();
+        expected[self.dim] = self.index.len() as u32;
+        if src.shape != expected {
+            return Err(RsgradError::shape("IndexAdd", &src.shape, &expected))
+        }
+        check_indices(&self.index, a.shape[self.dim])
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let mut result = x[0].borrow().contiguous();
+        let src = x[1].borrow();
+        assert!(self.dim < result.ndim(), "cannot index dimension {} of {:?}", self.dim, result.shape);
+        assert_eq!(src.shape[self.dim] as usize, self.index.len(), "{} indices for {} entries of {:?}", self.index.len(), src.shape[self.dim], src.shape);
+        for (flat, &value) in src.values().iter().enumerate() {
+            let mut position = unravel(flat, &src.shape);
+            position[self.dim] = self.index[position[self.dim] as usize];
+            *result.at(&position) += value;
+        }
+        result
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![SumTo::forward(grad.clone(), &x[0].borrow().shape), IndexSelect::forward(grad, self.dim, &self.index)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        IndexAdd::forward_nograd(tangents[0].clone(), self.dim, &self.index, tangents[1].clone())
+    }
+}
+
+/// Checks the layout shared by gather and scatter: `index` has as many dimensions as `a` and
+/// `src`, is no larger than either outside of `dim` and only holds positions inside `a`.
+fn check_scatter_layout<T: Float>(op: &str, a: &Tensor<T>, src: Option<&Tensor<T>>, dim: usize, index: &[u32], index_shape: &[u32]) -> Result<()> {
+    check_index(dim, a.ndim())?;
+    if a.ndim() != index_shape.len() {
+        return Err(RsgradError::rank(op, a.ndim(), index_shape.len()))
+    }
+    if index_shape.iter().zip(a.shape.iter()).enumerate().any(|(d, (&i, &size))| d != dim && i > size) {
+        return Err(RsgradError::shape(op, &a.shape, index_shape))
+    }
+    if let Some(src) = src {
+        if src.ndim() != index_shape.len() {
+            return Err(RsgradError::rank(op, src.ndim(), index_shape.len()))
+        }
+        if index_shape.iter().zip(src.shape.iter()).any(|(&i, &size)| i > size) {
+            return Err(RsgradError::shape(op, &src.shape, index_shape))
+        }
+    }
+    check_indices(index, a.shape[dim])
+}
+
+/// Reads `a` along `dim` at the positions held by `index`: for `dim` 1 of a matrix,
+/// `out[i][j] = a[i][index[i][j]]`. `index` has as many dimensions as `a` and the result takes
+/// its shape.
+#[derive(Clone)]
+pub struct Gather {
+    pub dim: usize,
+    pub index: Vec<u32>,
+    pub index_shape: Vec<u32>
+}
+
+impl Gather {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &Tensor<i64>)-> Tensor<T> {
+        Gather {dim, index: indices("Gather", index), index_shape: index.shape.clone()}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &Tensor<i64>)-> Tensor<T> {
+        apply(Rc::new(Gather {dim, index: indices("Gather", index), index_shape: index.shape.clone()}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &Tensor<i64>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Gather {dim, index: try_indices("Gather", index)?, index_shape: index.shape.clone()}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Gather {
+    fn name(&self) -> &str {
+        "Gather"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_scatter_layout("Gather", &x[0].borrow(), None, self.dim, &self.index, &self.index_shape)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        assert!(self.dim < a.ndim() && self.index_shape.len() == a.ndim(), "cannot gather {:?} along {} from {:?}", self.index_shape, self.dim, a.shape);
+        let buffer: Vec<T> = self.index.iter().enumerate().map(|(flat, &i)| {
+            let mut position = unravel(flat, &self.index_shape);
+            position[self.dim] = i;
+            *a.at_im(&position)
+        }).collect();
+        Tensor::new(buffer, &self.index_shape)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let zeros = rc(Tensor::constant_fill(T::zero(), &x[0].borrow().shape).with_requires_grad(false));
+        vec![apply(Rc::new(ScatterAdd {dim: self.dim, index: self.index.clone(), index_shape: self.index_shape.clone()}), vec![zeros, grad])]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// Adds `src` into `a` along `dim` at the positions held by `index`: for `dim` 1 of a matrix,
+/// `out[i][index[i][j]] += src[i][j]`. The adjoint of [`Gather`].
+#[derive(Clone)]
+pub struct ScatterAdd {
+    pub dim: usize,
+    pub index: Vec<u32>,
+    pub index_shape: Vec<u32>
+}
+
+impl ScatterAdd {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &Tensor<i64>, src: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        ScatterAdd {dim, index: indices("ScatterAdd", index), index_shape: index.shape.clone()}.forward(&[a, src])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &Tensor<i64>, src: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(ScatterAdd {dim, index: indices("ScatterAdd", index), index_shape: index.shape.clone()}), vec![a, src])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, index: &Tensor<i64>, src: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(ScatterAdd {dim, index: try_indices("ScatterAdd", index)?, index_shape: index.shape.clone()}), vec![a, src])
+    }
+}
+
+impl<T: Float> Function<T> for ScatterAdd {
+    fn name(&self) -> &str {
+        "ScatterAdd"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_scatter_layout("ScatterAdd", &x[0].borrow(), Some(&x[1].borrow()), self.dim, &self.index, &self.index_shape)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let mut result = x[0].borrow().contiguous();
+        let src = x[1].borrow();
+        assert!(self.dim < result.ndim() && self.index_shape.len() == result.ndim(), "cannot scatter {:?} along {} into {:?}", self.index_shape, self.dim, result.shape);
+        for (flat, &i) in self.index.iter().enumerate() {
+            let mut position = unravel(flat, &self.index_shape);
+            let value = *src.at_im(&position);
+            position[self.dim] = i;
+            *result.at(&position) += value;
+        }
+        result
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let gather = Gather {dim: self.dim, index: self.index.clone(), index_shape: self.index_shape.clone()};
+        vec![SumTo::forward(grad.clone(), &x[0].borrow().shape), apply(Rc::new(gather), vec![grad])]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// The elements of `a` where `mask` is set, flattened in row-major order. `mask` is
+/// broadcast to the shape of `a`.
+#[derive(Clone)]
+pub struct MaskedSelect {
+    pub mask: Vec<bool>
+}
+
+impl MaskedSelect {
+    fn new<T: Float>(a: &Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>) -> MaskedSelect {
+        or_panic(MaskedSelect::try_new(a, mask))
+    }
+
+    fn try_new<T: Float>(a: &Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>) -> Result<MaskedSelect> {
+        let mask = mask.try_expand(&a.borrow().shape)?;
+        Ok(MaskedSelect {mask: mask.values().into_owned()})
+    }
+
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>)-> Tensor<T> {
+        MaskedSelect::new(&a, mask).forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>)-> Tensor<T> {
+        apply(Rc::new(MaskedSelect::new(&a, mask)), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(MaskedSelect::try_new(&a, mask)?), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for MaskedSelect {
+    fn name(&self) -> &str {
+        "MaskedSelect"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let a = x[0].borrow();
+        if self.mask.len() != a.numel() {
+            return Err(RsgradError::shape("MaskedSelect", &a.shape, &[self.mask.len() as u32]))
+        }
+        Ok(())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let buffer: Vec<T> = x[0].borrow().values().iter().zip(self.mask.iter()).filter(|(_, &keep)| keep).map(|(&value, _)| value).collect();
+        let size = buffer.len() as u32;
+        Tensor::new(buffer, &[size])
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![MaskedScatter::forward(grad, &self.mask, &x[0].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// Writes the elements of a 1-d input, in order, to the positions of a zero tensor of `shape`
+/// where `mask` is set. The adjoint of [`MaskedSelect`].
+#[derive(Clone)]
+pub struct MaskedScatter {
+    pub mask: Vec<bool>,
+    pub shape: Vec<u32>
+}
+
+impl MaskedScatter {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &[bool], shape: &[u32])-> Tensor<T> {
+        MaskedScatter {mask: mask.to_vec(), shape: shape.to_vec()}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &[bool], shape: &[u32])-> Tensor<T> {
+        apply(Rc::new(MaskedScatter {mask: mask.to_vec(), shape: shape.to_vec()}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &[bool], shape: &[u32])-> Result<Tensor<T>> {
+        try_apply(Rc::new(MaskedScatter {mask: mask.to_vec(), shape: shape.to_vec()}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for MaskedScatter {
+    fn name(&self) -> &str {
+        "MaskedScatter"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let src = x[0].borrow();
+        let selected = self.mask.iter().filter(|&&keep| keep).count() as u32;
+        if numel(&self.shape) != self.mask.len() || src.shape != [selected] {
+            return Err(RsgradError::shape("MaskedScatter", &src.shape, &[selected]))
+        }
+        Ok(())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let src = x[0].borrow();
+        let src = src.values();
+        assert_eq!(src.len(), self.mask.iter().filter(|&&keep| keep).count(), "mask does not match the number of elements");
+        let mut values = src.iter();
+        let buffer: Vec<T> = self.mask.iter().map(|&keep| if keep {*values.next().unwrap()} else {T::zero()}).collect();
+        Tensor::new(buffer, &self.shape)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![apply(Rc::new(MaskedSelect {mask: self.mask.clone()}), vec![grad])]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// Folds the elements of `a` that share a position outside of `dims` with `f`, starting from
+/// `init`. The reduced dimensions are kept with size 1.
+fn reduce<T: Float>(a: &Tensor<T>, dims: &[usize], init: T, f: impl Fn(T, T) -> T) -> Tensor<T> {
+    let mut result = Tensor::constant_fill(init, &reduced_shape(&a.shape, dims));
+    let targets = result.broadcast_offsets(&a.shape);
+    for (target, &value) in targets.into_iter().zip(a.values().iter()) {
+        result.buffer[target] = f(result.buffer[target], value);
+    }
+    result
+}
+
+/// Drops the reduced dimensions of a `reduce` result unless they are to be kept.
+fn finish_reduce<T: Float>(result: Tensor<T>, a: &Tensor<T>, dims: &[usize], keepdim: bool) -> Tensor<T> {
+    if keepdim {
+        return result
+    }
+    let shape: Vec<u32> = a.shape.iter().enumerate().filter(|(dim, _)| !dims.contains(dim)).map(|(_, &size)| size).collect();
+    result.reshape(&shape)
+}
+
+/// Brings the grad of a reduction back to the shape of its input `a`, repeating it along `dims`.
+fn expand_reduced<T: Float>(grad: Rc<RefCell<Tensor<T>>>, a: &Tensor<T>, dims: &[usize]) -> Tensor<T> {
+    let kept = rc(Reshape::forward(grad, &reduced_shape(&a.shape, dims)));
+    Expand::forward(kept, &a.shape)
+}
+
+/// Marks with 1 the first element of every group reduced over `dims` that no other element of
+/// the group [`beats`], which is the first NaN when the group has one.
+fn extreme_mask<T: Float>(a: &Tensor<T>, dims: &[usize], better: impl Fn(T, T) -> bool) -> Tensor<T> {
+    let reduced = reduced_shape(&a.shape, dims);
+    let mut best: Vec<Option<(usize, T)>> = vec![None; crate::tensor::numel(&reduced)];
+    let targets = Tensor::constant_fill(T::zero(), &reduced).broadcast_offsets(&a.shape);
+    for (flat, (target, &value)) in targets.into_iter().zip(a.values().iter()).enumerate() {
+        if best[target].is_none_or(|(_, current)| beats(value, current, &better)) {
+            best[target] = Some((flat, value));
+        }
+    }
+    let mut mask = Tensor::constant_fill(T::zero(), &a.shape);
+    for (flat, _) in best.into_iter().flatten() {
+        mask.buffer[flat] = T::one();
+    }
+    mask
+}
+
+/// Sums over `dims`, which are dropped from the result unless `keepdim` is set.
+#[derive(Clone)]
+pub struct SumDim {
+    pub dims: Vec<usize>,
+    pub keepdim: bool
+}
+
+impl SumDim {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        SumDim {dims: dims.to_vec(), keepdim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        apply(Rc::new(SumDim {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Result<Tensor<T>> {
+        try_apply(Rc::new(SumDim {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for SumDim {
+    fn name(&self) -> &str {
+        "SumDim"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        try_reduced_shape(&x[0].borrow().shape, &self.dims).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        finish_reduce(reduce(&a, &self.dims, T::zero(), |acc, value| acc+value), &a, &self.dims, self.keepdim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![expand_reduced(grad, &x[0].borrow(), &self.dims)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        SumDim::forward_nograd(tangents[0].clone(), &self.dims, self.keepdim)
+    }
+}
+
+/// Averages over `dims`, which are dropped from the result unless `keepdim` is set.
+#[derive(Clone)]
+pub struct Mean {
+    pub dims: Vec<usize>,
+    pub keepdim: bool
+}
+
+impl Mean {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        Mean {dims: dims.to_vec(), keepdim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        apply(Rc::new(Mean {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Mean {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+
+    fn count<T: Float>(&self, a: &Tensor<T>) -> T {
+        T::from_f64(self.dims.iter().map(|&dim| a.shape[dim] as f64).product())
+    }
+}
+
+impl<T: Float> Function<T> for Mean {
+    fn name(&self) -> &str {
+        "Mean"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        try_reduced_shape(&x[0].borrow().shape, &self.dims).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        let count = self.count(&a);
+        let mut sums = reduce(&a, &self.dims, T::zero(), |acc, value| acc+value);
+        for value in sums.buffer.iter_mut() {
+            *value /= count;
+        }
+        finish_reduce(sums, &a, &self.dims, self.keepdim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let a = x[0].borrow();
+        let expanded = rc(expand_reduced(grad, &a, &self.dims));
+        vec![Mult::forward(expanded, rc(Tensor::scalar(T::one()/self.count(&a))))]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Mean::forward_nograd(tangents[0].clone(), &self.dims, self.keepdim)
+    }
+}
+
+/// Largest value over `dims`, NaN if the group holds one. The grad flows only to the first
+/// element holding the maximum in each reduced group.
+#[derive(Clone)]
+pub struct Max {
+    pub dims: Vec<usize>,
+    pub keepdim: bool
+}
+
+impl Max {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        Max {dims: dims.to_vec(), keepdim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        apply(Rc::new(Max {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Max {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Max {
+    fn name(&self) -> &str {
+        "Max"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        try_reduced_shape(&x[0].borrow().shape, &self.dims).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        let largest = |best, value| if beats(value, best, |value, best| value > best) {value} else {best};
+        finish_reduce(reduce(&a, &self.dims, T::neg_infinity(), largest), &a, &self.dims, self.keepdim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let a = x[0].borrow();
+        let mask = rc(extreme_mask(&a, &self.dims, |value, best| value > best));
+        vec![Mult::forward(rc(expand_reduced(grad, &a, &self.dims)), mask)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let mask = rc(extreme_mask(&x[0].borrow(), &self.dims, |value, best| value > best));
+        SumDim::forward_nograd(rc(Mult::forward_nograd(tangents[0].clone(), mask)), &self.dims, self.keepdim)
+    }
+}
+
+/// Smallest value over `dims`, NaN if the group holds one. The grad flows only to the first
+/// element holding the minimum in each reduced group.
+#[derive(Clone)]
+pub struct Min {
+    pub dims: Vec<usize>,
+    pub keepdim: bool
+}
+
+impl Min {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        Min {dims: dims.to_vec(), keepdim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        apply(Rc::new(Min {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Min {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Min {
+    fn name(&self) -> &str {
+        "Min"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        try_reduced_shape(&x[0].borrow().shape, &self.dims).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        let smallest = |best, value| if beats(value, best, |value, best| value < best) {value} else {best};
+        finish_reduce(reduce(&a, &self.dims, T::infinity(), smallest), &a, &self.dims, self.keepdim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let a = x[0].borrow();
+        let mask = rc(extreme_mask(&a, &self.dims, |value, best| value < best));
+        vec![Mult::forward(rc(expand_reduced(grad, &a, &self.dims)), mask)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let mask = rc(extreme_mask(&x[0].borrow(), &self.dims, |value, best| value < best));
+        SumDim::forward_nograd(rc(Mult::forward_nograd(tangents[0].clone(), mask)), &self.dims, self.keepdim)
+    }
+}
+
+/// Product over `dims`, which are dropped from the result unless `keepdim` is set.
+#[derive(Clone)]
+pub struct Prod {
+    pub dims: Vec<usize>,
+    pub keepdim: bool
+}
+
+impl Prod {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        Prod {dims: dims.to_vec(), keepdim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Tensor<T> {
+        apply(Rc::new(Prod {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize], keepdim: bool)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Prod {dims: dims.to_vec(), keepdim}), vec![a])
+    }
+
+    /// For every element, the product of the other elements of its group, built from recording
+    /// ops. The groups are flattened into the last dimension, where the product of everything
+    /// before an element times the product of everything after it avoids dividing by zeros.
+    fn others<T: Float>(a: Rc<RefCell<Tensor<T>>>, dims: &[usize]) -> Tensor<T> {
+        let shape = a.borrow().shape.clone();
+        let kept: Vec<usize> = (0..shape.len()).filter(|dim| !dims.contains(dim)).collect();
+        let order: Vec<usize> = kept.iter().chain(dims.iter()).copied().collect();
+        let size: u32 = dims.iter().map(|&dim| shape[dim]).product();
+        let last = kept.len();
+        let mut flat_shape: Vec<u32> = kept.iter().map(|&dim| shape[dim]).collect();
+        flat_shape.push(size);
+        let flat = rc(Reshape::forward(rc(Permute::forward(a, &order)), &flat_shape));
+
+        let reversed: Vec<u32> = (0..size).rev().collect();
+        let before = Prod::exclusive_cumprod(flat.clone(), last);
+        let after = Prod::exclusive_cumprod(rc(IndexSelect::forward(flat, last, &reversed)), last);
+        let after = rc(IndexSelect::forward(after, last, &reversed));
+        let permuted_shape: Vec<u32> = order.iter().map(|&dim| shape[dim]).collect();
+        let others = rc(Reshape::forward(rc(Mult::forward(before, after)), &permuted_shape));
+        let mut inverse = vec![0; order.len()];
+        for (position, &dim) in order.iter().enumerate() {
+            inverse[dim] = position;
+        }
+        Permute::forward(others, &inverse)
+    }
+
+    /// The product of the elements before each one along `dim`, one for the first.
+    fn exclusive_cumprod<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize) -> Rc<RefCell<Tensor<T>>> {
+        let mut first_shape = a.borrow().shape.clone();
+        let size = first_shape[dim];
+        first_shape[dim] = 1;
+        let head = rc(Slice::forward(a, dim, 0, size - 1, 1));
+        let ones = rc(Tensor::constant_fill(T::one(), &first_shape));
+        rc(Cat::forward(vec![ones, rc(CumProd::forward(head, dim))], dim))
+    }
+}
+
+impl<T: Float> Function<T> for Prod {
+    fn name(&self) -> &str {
+        "Prod"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        try_reduced_shape(&x[0].borrow().shape, &self.dims).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        finish_reduce(reduce(&a, &self.dims, T::one(), |acc, value| acc*value), &a, &self.dims, self.keepdim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let a = x[0].borrow();
+        let expanded = rc(expand_reduced(grad, &a, &self.dims));
+        if a.values().contains(&T::zero()) {
+            // out/x breaks down at zeros, fall back to products of the other elements
+            return vec![Mult::forward(expanded, rc(Prod::others(x[0].clone(), &self.dims)))]
+        }
+        let out = rc(Prod::forward(x[0].clone(), &self.dims, true));
+        let others = rc(Div::forward(rc(Expand::forward(out, &a.shape)), x[0].clone()));
+        vec![Mult::forward(expanded, others)]
+    }
+}
+
+/// Joins the inputs along the existing dimension `dim`. All other dimensions have to match.
+#[derive(Clone)]
+pub struct Cat {
+    pub dim: usize
+}
+
+impl Cat {
+    pub fn forward_nograd<T: Float>(x: Vec<Rc<RefCell<Tensor<T>>>>, dim: usize)-> Tensor<T> {
+        Cat {dim}.forward(&x)
+    }
+
+    pub fn forward<T: Float>(x: Vec<Rc<RefCell<Tensor<T>>>>, dim: usize)-> Tensor<T> {
+        apply(Rc::new(Cat {dim}), x)
+    }
+
+    pub fn try_forward<T: Float>(x: Vec<Rc<RefCell<Tensor<T>>>>, dim: usize)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Cat {dim}), x)
+    }
+}
+
+/// Copies `parts` one after the other along `dim` into a new tensor.
+fn concatenate<T: Float>(parts: &[&Tensor<T>], dim: usize) -> Tensor<T> {
+    assert!(!parts.is_empty(), "nothing to concatenate");
+    let mut shape = parts[0].shape.clone();
+    assert!(dim < shape.len(), "cannot concatenate along dimension {} of {:?}", dim, shape);
+    shape[dim] = 0;
+    for part in parts {
+        let mut expected = shape.clone();
+        expected[dim] = part.shape[dim];
+        assert_eq!(part.shape, expected, "cannot concatenate {:?} with {:?} along dimension {}", part.shape, parts[0].shape, dim);
+        shape[dim] += part.shape[dim];
+    }
+    let mut result = Tensor::constant_fill(T::zero(), &shape);
+    let mut start = 0;
+    for part in parts {
+        let offsets = result.slice(dim, start, start + part.shape[dim], 1).offsets();
+        for (offset, &value) in offsets.into_iter().zip(part.values().iter()) {
+            result.buffer[offset] = value;
+        }
+        start += part.shape[dim];
+    }
+    result
+}
+
+impl<T: Float> Function<T> for Cat {
+    fn name(&self) -> &str {
+        "Cat"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let first = x.first().ok_or(RsgradError::index(0, 0))?.borrow();
+        check_index(self.dim, first.ndim())?;
+        for part in x {
+            let part = part.borrow();
+            if part.ndim() != first.ndim() {
+                return Err(RsgradError::rank("Cat", first.ndim(), part.ndim()))
+            }
+            if (0..first.ndim()).any(|dim| dim != self.dim && part.shape[dim] != first.shape[dim]) {
+                return Err(RsgradError::shape("Cat", &first.shape, &part.shape))
+            }
+        }
+        Ok(())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let parts: Vec<_> = x.iter().map(|part| part.borrow()).collect();
+        concatenate(&parts.iter().map(|part| &**part).collect::<Vec<&Tensor<T>>>(), self.dim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let sizes: Vec<u32> = x.iter().map(|part| part.borrow().shape[self.dim]).collect();
+        split(grad, self.dim, &sizes)
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Cat::forward_nograd(tangents.to_vec(), self.dim)
+    }
+}
+
+/// Joins equally shaped inputs along a new dimension inserted at `dim`.
+#[derive(Clone)]
+pub struct Stack {
+    pub dim: usize
+}
+
+impl Stack {
+    pub fn forward_nograd<T: Float>(x: Vec<Rc<RefCell<Tensor<T>>>>, dim: usize)-> Tensor<T> {
+        Stack {dim}.forward(&x)
+    }
+
+    pub fn forward<T: Float>(x: Vec<Rc<RefCell<Tensor<T>>>>, dim: usize)-> Tensor<T> {
+        apply(Rc::new(Stack {dim}), x)
+    }
+
+    pub fn try_forward<T: Float>(x: Vec<Rc<RefCell<Tensor<T>>>>, dim: usize)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Stack {dim}), x)
+    }
+}
+
+impl<T: Float> Function<T> for Stack {
+    fn name(&self) -> &str {
+        "Stack"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let first = x.first().ok_or(RsgradError::index(0, 0))?.borrow();
+        check_index(self.dim, first.ndim() + 1)?;
+        for part in x {
+            if part.borrow().shape != first.shape {
+                return Err(RsgradError::shape("Stack", &first.shape, &part.borrow().shape))
+            }
+        }
+        Ok(())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let parts: Vec<Tensor<T>> = x.iter().map(|part| part.borrow().unsqueeze(self.dim)).collect();
+        concatenate(&parts.iter().collect::<Vec<&Tensor<T>>>(), self.dim)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        split(grad, self.dim, &vec![1; x.len()]).into_iter().map(|part| Squeeze::forward(rc(part), self.dim)).collect()
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Stack::forward_nograd(tangents.to_vec(), self.dim)
+    }
+}
+
+/// Cuts `a` along `dim` into consecutive views of the given `sizes`, which have to add up to
+/// the size of that dimension.
+pub fn split<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, sizes: &[u32]) -> Vec<Tensor<T>> {
+    or_panic(try_split(a, dim, sizes))
+}
+
+pub fn try_split<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, sizes: &[u32]) -> Result<Vec<Tensor<T>>> {
+    let shape = a.borrow().shape.clone();
+    check_index(dim, shape.len())?;
+    if sizes.iter().sum::<u32>() != shape[dim] {
+        return Err(RsgradError::shape("split", &shape[dim..dim+1], &[sizes.iter().sum::<u32>()]))
+    }
+    let mut start = 0;
+    sizes.iter().map(|&size| {
+        start += size;
+        Slice::try_forward(a.clone(), dim, start - size, start, 1)
+    }).collect()
+}
+
+/// Cuts `a` along `dim` into `chunks` views of equal size, rounded up, so the last one may be
+/// smaller and there may be fewer than `chunks` of them. `chunks` has to be positive.
+pub fn chunk<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, chunks: u32) -> Vec<Tensor<T>> {
+    or_panic(try_chunk(a, dim, chunks))
+}
+
+pub fn try_chunk<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, chunks: u32) -> Result<Vec<Tensor<T>>> {
+    if chunks == 0 {
+        return Err(RsgradError::invalid("chunk", "cannot cut into zero chunks"))
+    }
+    check_index(dim, a.borrow().ndim())?;
+    let total = a.borrow().shape[dim];
+    let size = total.div_ceil(chunks).max(1);
+    let mut sizes: Vec<u32> = vec![size; (total / size) as usize];
+    if !total.is_multiple_of(size) {
+        sizes.push(total % size);
+    }
+    try_split(a, dim, &sizes)
+}
+
+#[derive(Clone)]
+pub struct Neg;
+
+impl Neg {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Neg.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Neg), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Neg), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Neg {
+    fn name(&self) -> &str {
+        "Neg"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), |x| -x)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Neg::forward(grad)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Neg::forward_nograd(tangents[0].clone())
+    }
+}
+
+#[derive(Clone)]
+pub struct Exp;
+
+impl Exp {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Exp.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Exp), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Exp), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Exp {
+    fn name(&self) -> &str {
+        "Exp"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::exp)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Mult::forward(grad, rc(Exp::forward(x[0].clone())))]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        zip_map(&tangents[0].borrow(), out, |t, o| t*o)
+    }
+}
+
+#[derive(Clone)]
+pub struct Sqrt;
+
+impl Sqrt {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Sqrt.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Sqrt), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Sqrt), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Sqrt {
+    fn name(&self) -> &str {
+        "Sqrt"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::sqrt)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let doubled = rc(Mult::forward(rc(Sqrt::forward(x[0].clone())), constant(2.0)));
+        vec![Div::forward(grad, doubled)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        zip_map(&tangents[0].borrow(), out, |t, o| t/(o+o))
+    }
+}
+
+/// Raises `a` to the power `b` elementwise, with broadcasting.
+#[derive(Clone)]
+pub struct Pow;
+
+impl Pow {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Pow.forward(&[a, b])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Pow), vec![a, b])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Pow), vec![a, b])
+    }
+}
+
+impl<T: Float> Function<T> for Pow {
+    fn name(&self) -> &str {
+        "Pow"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_broadcast(x)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        zip_map(&x[0].borrow(), &x[1].borrow(), T::powf)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        // d(a^b)/da = b*a^(b-1), d(a^b)/db = a^b*ln(a)
+        let lowered = rc(Sub::forward(x[1].clone(), constant(1.0)));
+        let power = rc(Pow::forward(x[0].clone(), lowered));
+        let grad_a = rc(Mult::forward(grad.clone(), rc(Mult::forward(x[1].clone(), power))));
+        let out = rc(Pow::forward(x[0].clone(), x[1].clone()));
+        let grad_b = rc(Mult::forward(grad, rc(Mult::forward(out, rc(Log::forward(x[0].clone()))))));
+        vec![SumTo::forward(grad_a, &x[0].borrow().shape), SumTo::forward(grad_b, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        let slope = rc(zip_map(&x[0].borrow(), &x[1].borrow(), |a, b| b*a.powf(b-T::one())));
+        let log_a = rc(map(&x[0].borrow(), T::ln));
+        let left = rc(Mult::forward_nograd(tangents[0].clone(), slope));
+        let right = rc(zip_map(&Mult::forward_nograd(tangents[1].clone(), log_a), out, |t, o| t*o));
+        Add::forward_nograd(left, right)
+    }
+}
+
+/// Raises the input to a fixed power.
+#[derive(Clone)]
+pub struct PowScalar {
+    pub exponent: f64
+}
+
+impl PowScalar {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, exponent: f64)-> Tensor<T> {
+        PowScalar {exponent}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, exponent: f64)-> Tensor<T> {
+        apply(Rc::new(PowScalar {exponent}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, exponent: f64)-> Result<Tensor<T>> {
+        try_apply(Rc::new(PowScalar {exponent}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for PowScalar {
+    fn name(&self) -> &str {
+        "PowScalar"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let exponent = T::from_f64(self.exponent);
+        map(&x[0].borrow(), |x| x.powf(exponent))
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let power = rc(PowScalar::forward(x[0].clone(), self.exponent - 1.0));
+        let slope = rc(Mult::forward(power, constant(self.exponent)));
+        vec![Mult::forward(grad, slope)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let exponent = T::from_f64(self.exponent);
+        zip_map(&tangents[0].borrow(), &x[0].borrow(), |t, x| t*exponent*x.powf(exponent - T::one()))
+    }
+}
+
+/// Absolute value, with a grad of 0 at 0.
+#[derive(Clone)]
+pub struct Abs;
+
+impl Abs {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Abs.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Abs), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Abs), vec![a])
+    }
+
+    fn sign<T: Float>(a: &Tensor<T>) -> Tensor<T> {
+        map(a, |x| if x > T::zero() {T::one()} else if x < T::zero() {-T::one()} else {T::zero()})
+    }
+}
+
+impl<T: Float> Function<T> for Abs {
+    fn name(&self) -> &str {
+        "Abs"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::abs)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Mult::forward(grad, rc(Abs::sign(&x[0].borrow())))]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Mult::forward_nograd(tangents[0].clone(), rc(Abs::sign(&x[0].borrow())))
+    }
+}
+
+#[derive(Clone)]
+pub struct Sin;
+
+impl Sin {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Sin.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Sin), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Sin), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Sin {
+    fn name(&self) -> &str {
+        "Sin"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::sin)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Mult::forward(grad, rc(Cos::forward(x[0].clone())))]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        zip_map(&tangents[0].borrow(), &x[0].borrow(), |t, x| t*x.cos())
+    }
+}
+
+#[derive(Clone)]
+pub struct Cos;
+
+impl Cos {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Cos.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Cos), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Cos), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Cos {
+    fn name(&self) -> &str {
+        "Cos"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::cos)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Mult::forward(grad, rc(Neg::forward(rc(Sin::forward(x[0].clone())))))]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        zip_map(&tangents[0].borrow(), &x[0].borrow(), |t, x| -t*x.sin())
+    }
+}
+
+#[derive(Clone)]
+pub struct Tanh;
+
+impl Tanh {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Tanh.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Tanh), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Tanh), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Tanh {
+    fn name(&self) -> &str {
+        "Tanh"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::tanh)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        // 1 - tanh(x)^2
+        let out = rc(Tanh::forward(x[0].clone()));
+        let slope = rc(Sub::forward(constant(1.0), rc(Mult::forward(out.clone(), out))));
+        vec![Mult::forward(grad, slope)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        zip_map(&tangents[0].borrow(), out, |t, o| t*(T::one() - o*o))
+    }
+}
+
+#[derive(Clone)]
+pub struct Sigmoid;
+
+impl Sigmoid {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Sigmoid.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Sigmoid), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Sigmoid), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Sigmoid {
+    fn name(&self) -> &str {
+        "Sigmoid"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), |x| T::one()/(T::one() + (-x).exp()))
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        // sigmoid(x)*(1 - sigmoid(x))
+        let out = rc(Sigmoid::forward(x[0].clone()));
+        let slope = rc(Mult::forward(out.clone(), rc(Sub::forward(constant(1.0), out))));
+        vec![Mult::forward(grad, slope)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        zip_map(&tangents[0].borrow(), out, |t, o| t*o*(T::one() - o))
+    }
+}
+
+/// Limits the input to `[min, max]`. The grad passes where the input lies inside the bounds.
+#[derive(Clone)]
+pub struct Clamp {
+    pub min: f64,
+    pub max: f64
+}
+
+impl Clamp {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, min: f64, max: f64)-> Tensor<T> {
+        Clamp {min, max}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, min: f64, max: f64)-> Tensor<T> {
+        apply(Rc::new(Clamp {min, max}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, min: f64, max: f64)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Clamp {min, max}), vec![a])
+    }
+
+    fn mask<T: Float>(&self, a: &Tensor<T>) -> Tensor<T> {
+        let (min, max) = (T::from_f64(self.min), T::from_f64(self.max));
+        map(a, |x| if x >= min && x <= max {T::one()} else {T::zero()})
+    }
+}
+
+impl<T: Float> Function<T> for Clamp {
+    fn name(&self) -> &str {
+        "Clamp"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let (min, max) = (T::from_f64(self.min), T::from_f64(self.max));
+        map(&x[0].borrow(), |x| x.max(min).min(max))
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![Mult::forward(grad, rc(self.mask(&x[0].borrow())))]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        Mult::forward_nograd(tangents[0].clone(), rc(self.mask(&x[0].borrow())))
+    }
+}
+
+#[derive(Clone)]
+pub struct Reciprocal;
+
+impl Reciprocal {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Reciprocal.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Reciprocal), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Reciprocal), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for Reciprocal {
+    fn name(&self) -> &str {
+        "Reciprocal"
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        map(&x[0].borrow(), T::recip)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let squared = rc(Mult::forward(x[0].clone(), x[0].clone()));
+        vec![Neg::forward(rc(Div::forward(grad, squared)))]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        zip_map(&tangents[0].borrow(), out, |t, o| -t*o*o)
+    }
+}
+
+/// Share of the grad of an elementwise maximum or minimum that goes to `a`: all of it where
+/// `a` is picked, none where `b` is and half on ties.
+fn pick_share<T: Float>(a: &Tensor<T>, b: &Tensor<T>, picks_a: impl Fn(T, T) -> bool) -> Tensor<T> {
+    let half = T::from_f64(0.5);
+    zip_map(a, b, |x, y| if x == y {half} else if picks_a(x, y) {T::one()} else {T::zero()})
+}
+
+/// Elementwise larger of `a` and `b`, with broadcasting.
+#[derive(Clone)]
+pub struct Maximum;
+
+impl Maximum {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Maximum.forward(&[a, b])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Maximum), vec![a, b])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Maximum), vec![a, b])
+    }
+}
+
+impl<T: Float> Function<T> for Maximum {
+    fn name(&self) -> &str {
+        "Maximum"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_broadcast(x)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        zip_map(&x[0].borrow(), &x[1].borrow(), T::max)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let share_a = pick_share(&x[0].borrow(), &x[1].borrow(), |a, b| a > b);
+        let share_b = rc(map(&share_a, |share| T::one() - share));
+        let grad_a = rc(Mult::forward(grad.clone(), rc(share_a)));
+        let grad_b = rc(Mult::forward(grad, share_b));
+        vec![SumTo::forward(grad_a, &x[0].borrow().shape), SumTo::forward(grad_b, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let share_a = pick_share(&x[0].borrow(), &x[1].borrow(), |a, b| a > b);
+        let share_b = rc(map(&share_a, |share| T::one() - share));
+        let left = rc(Mult::forward_nograd(tangents[0].clone(), rc(share_a)));
+        let right = rc(Mult::forward_nograd(tangents[1].clone(), share_b));
+        Add::forward_nograd(left, right)
+    }
+}
+
+/// Elementwise smaller of `a` and `b`, with broadcasting.
+#[derive(Clone)]
+pub struct Minimum;
+
+impl Minimum {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Minimum.forward(&[a, b])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Minimum), vec![a, b])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, b: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Minimum), vec![a, b])
+    }
+}
+
+impl<T: Float> Function<T> for Minimum {
+    fn name(&self) -> &str {
+        "Minimum"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_broadcast(x)
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        zip_map(&x[0].borrow(), &x[1].borrow(), T::min)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let share_a = pick_share(&x[0].borrow(), &x[1].borrow(), |a, b| a < b);
+        let share_b = rc(map(&share_a, |share| T::one() - share));
+        let grad_a = rc(Mult::forward(grad.clone(), rc(share_a)));
+        let grad_b = rc(Mult::forward(grad, share_b));
+        vec![SumTo::forward(grad_a, &x[0].borrow().shape), SumTo::forward(grad_b, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        let share_a = pick_share(&x[0].borrow(), &x[1].borrow(), |a, b| a < b);
+        let share_b = rc(map(&share_a, |share| T::one() - share));
+        let left = rc(Mult::forward_nograd(tangents[0].clone(), rc(share_a)));
+        let right = rc(Mult::forward_nograd(tangents[1].clone(), share_b));
+        Add::forward_nograd(left, right)
+    }
+}
+
+/// Elementwise `on_true` where `cond` is set and `on_false` elsewhere, with `cond`, `on_true`
+/// and `on_false` broadcast to a common shape. The condition itself is not differentiated.
+#[derive(Clone)]
+pub struct Where {
+    pub cond: Tensor<bool>
+}
+
+impl Where {
+    pub fn forward_nograd<T: Float>(cond: &Tensor<bool>, on_true: Rc<RefCell<Tensor<T>>>, on_false: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        Where {cond: cond.clone()}.forward(&[on_true, on_false])
+    }
+
+    pub fn forward<T: Float>(cond: &Tensor<bool>, on_true: Rc<RefCell<Tensor<T>>>, on_false: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(Where {cond: cond.clone()}), vec![on_true, on_false])
+    }
+
+    pub fn try_forward<T: Float>(cond: &Tensor<bool>, on_true: Rc<RefCell<Tensor<T>>>, on_false: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(Where {cond: cond.clone()}), vec![on_true, on_false])
+    }
+
+    fn try_shape<T: Float>(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<Vec<u32>> {
+        let shape = try_broadcast_shape(&self.cond.shape, &x[0].borrow().shape)?;
+        try_broadcast_shape(&shape, &x[1].borrow().shape)
+    }
+}
+
+impl<T: Float> Function<T> for Where {
+    fn name(&self) -> &str {
+        "Where"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        self.try_shape(x).map(|_| ())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let shape = or_panic(self.try_shape(x));
+        let (on_true, on_false) = (x[0].borrow(), x[1].borrow());
+        let cond = self.cond.broadcast_offsets(&shape);
+        let true_offsets = on_true.broadcast_offsets(&shape);
+        let false_offsets = on_false.broadcast_offsets(&shape);
+        let buffer: Vec<T> = cond.iter().zip(true_offsets.iter().zip(false_offsets.iter()))
+            .map(|(&c, (&i, &j))| if self.cond.buffer[c] {on_true.buffer[i]} else {on_false.buffer[j]})
+            .collect();
+        Tensor::new(buffer, &shape)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let grad_true = rc(Where::forward(&self.cond, grad.clone(), constant(0.0)));
+        let grad_false = rc(Where::forward(&self.cond, constant(0.0), grad));
+        vec![SumTo::forward(grad_true, &x[0].borrow().shape), SumTo::forward(grad_false, &x[1].borrow().shape)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// Replaces the elements of `a` where `mask`, broadcast to the shape of `a`, is set with the
+/// 0-d `value`. The grad of `a` flows to the elements that were kept and the grad of `value` is
+/// the sum over the ones that were filled.
+#[derive(Clone)]
+pub struct MaskedFill {
+    pub mask: Vec<bool>
+}
+
+impl MaskedFill {
+    fn new<T: Float>(a: &Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>) -> MaskedFill {
+        or_panic(MaskedFill::try_new(a, mask))
+    }
+
+    fn try_new<T: Float>(a: &Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>) -> Result<MaskedFill> {
+        let mask = mask.try_expand(&a.borrow().shape)?;
+        Ok(MaskedFill {mask: mask.values().into_owned()})
+    }
+
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>, value: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        MaskedFill::new(&a, mask).forward(&[a, value])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>, value: Rc<RefCell<Tensor<T>>>)-> Tensor<T> {
+        apply(Rc::new(MaskedFill::new(&a, mask)), vec![a, value])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, mask: &Tensor<bool>, value: Rc<RefCell<Tensor<T>>>)-> Result<Tensor<T>> {
+        try_apply(Rc::new(MaskedFill::try_new(&a, mask)?), vec![a, value])
+    }
+
+    /// The same op with the mask flipped, which keeps exactly the elements that were filled.
+    fn inverted(&self) -> MaskedFill {
+        MaskedFill {mask: self.mask.iter().map(|&fill| !fill).collect()}
+    }
+}
+
+impl<T: Float> Function<T> for MaskedFill {
+    fn name(&self) -> &str {
+        "MaskedFill"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        let (a, value) = (x[0].borrow(), x[1].borrow());
+        if self.mask.len() != a.numel() {
+            return Err(RsgradError::shape("MaskedFill", &a.shape, &[self.mask.len() as u32]))
+        }
+        if value.ndim() != 0 {
+            return Err(RsgradError::shape("MaskedFill", &value.shape, &[]))
+        }
+        Ok(())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        let a = x[0].borrow();
+        let value = x[1].borrow().item();
+        let buffer: Vec<T> = a.values().iter().zip(self.mask.iter()).map(|(&x, &fill)| if fill {value} else {x}).collect();
+        Tensor::new(buffer, &a.shape)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        let filled = rc(apply(Rc::new(self.inverted()), vec![grad.clone(), constant(0.0)]));
+        vec![apply(Rc::new(self.clone()), vec![grad, constant(0.0)]), Sum::forward(filled)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// Sorts `a` along `dim`, ascending unless `descending`, returning the sorted values along with
+/// the positions they were taken from. The values are a [`Gather`] of `a`, so grads flow back to
+/// where each element came from.
+pub fn sort<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, descending: bool) -> (Tensor<T>, Tensor<i64>) {
+    or_panic(try_sort(a, dim, descending))
+}
+
+pub fn try_sort<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, descending: bool) -> Result<(Tensor<T>, Tensor<i64>)> {
+    let indices = a.borrow().try_argsort(dim, descending)?;
+    Ok((Gather::try_forward(a, dim, &indices)?, indices))
+}
+
+/// The `k` largest elements along `dim`, or the smallest unless `largest`, in sorted order and
+/// with their positions. Differentiable like [`sort`].
+pub fn topk<T: Float>(a: Rc<RefCell<Tensor<T>>>, k: u32, dim: usize, largest: bool) -> (Tensor<T>, Tensor<i64>) {
+    or_panic(try_topk(a, k, dim, largest))
+}
+
+pub fn try_topk<T: Float>(a: Rc<RefCell<Tensor<T>>>, k: u32, dim: usize, largest: bool) -> Result<(Tensor<T>, Tensor<i64>)> {
+    let indices = a.borrow().try_argsort(dim, largest)?.try_slice(dim, 0, k, 1)?.contiguous();
+    Ok((Gather::try_forward(a, dim, &indices)?, indices))
+}
+
+/// Runs `f` along every lane of `dim`, carrying the result of one element into the next.
+fn scan<T: Float>(a: &Tensor<T>, dim: usize, reverse: bool, f: impl Fn(T, T) -> T) -> Tensor<T> {
+    let values = a.values();
+    let mut buffer = values.to_vec();
+    for mut lane in lanes(&a.shape, dim) {
+        if reverse {
+            lane.reverse();
+        }
+        for k in 1..lane.len() {
+            buffer[lane[k]] = f(buffer[lane[k-1]], values[lane[k]]);
+        }
+    }
+    Tensor::new(buffer, &a.shape)
+}
+
+/// Running sum along `dim`, from the last element backwards when `reverse` is set. Each
+/// direction is the adjoint of the other.
+#[derive(Clone)]
+pub struct CumSum {
+    pub dim: usize,
+    pub reverse: bool
+}
+
+impl CumSum {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, reverse: bool)-> Tensor<T> {
+        CumSum {dim, reverse}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, reverse: bool)-> Tensor<T> {
+        apply(Rc::new(CumSum {dim, reverse}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize, reverse: bool)-> Result<Tensor<T>> {
+        try_apply(Rc::new(CumSum {dim, reverse}), vec![a])
+    }
+}
+
+impl<T: Float> Function<T> for CumSum {
+    fn name(&self) -> &str {
+        "CumSum"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_index(self.dim, x[0].borrow().ndim())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        scan(&x[0].borrow(), self.dim, self.reverse, |acc, x| acc + x)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, _x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        vec![CumSum::forward(grad, self.dim, !self.reverse)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], _x: &[Rc<RefCell<Tensor<T>>>], _out: &Tensor<T>) -> Tensor<T> {
+        self.forward(tangents)
+    }
+}
+
+/// Running product along `dim`. The vjp divides by the input when it has no zeros and otherwise
+/// spells out every partial derivative, which is quadratic in the size of `dim`. Both are built
+/// from recording ops, so they can be differentiated again.
+#[derive(Clone)]
+pub struct CumProd {
+    pub dim: usize
+}
+
+impl CumProd {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        CumProd {dim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        apply(Rc::new(CumProd {dim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Result<Tensor<T>> {
+        try_apply(Rc::new(CumProd {dim}), vec![a])
+    }
+
+    /// The partial derivatives `d out[j] / d a[i]`, the product of `a[..=j]` without `a[i]`,
+    /// with `i` along `dim` and `j` along a new dimension right after it. Built from recording
+    /// ops by replacing `a[i]` with one in the `i`-th copy of `a` and zeroing `j < i`.
+    fn partials<T: Float>(&self, a: Rc<RefCell<Tensor<T>>>) -> Rc<RefCell<Tensor<T>>> {
+        let mut shape = a.borrow().shape.clone();
+        let size = shape[self.dim];
+        shape.insert(self.dim, size);
+        let mut pair_shape = vec![1; shape.len()];
+        pair_shape[self.dim] = size;
+        pair_shape[self.dim + 1] = size;
+        let pairs = |keep: fn(u32, u32) -> bool| {
+            let buffer: Vec<bool> = (0..size*size).map(|flat| keep(flat/size, flat % size)).collect();
+            Tensor::new(buffer, &pair_shape)
+        };
+        let copies = rc(Expand::forward(rc(Unsqueeze::forward(a, self.dim)), &shape));
+        let without = rc(MaskedFill::forward(copies, &pairs(|i, j| i == j), constant(1.0)));
+        let products = rc(CumProd::forward(without, self.dim + 1));
+        rc(MaskedFill::forward(products, &pairs(|i, j| j < i), constant(0.0)))
+    }
+
+    /// The jvp for inputs holding zeros, summing the tangents times the partial derivatives
+    /// directly.
+    fn tangent_partials<T: Float>(&self, a: &Tensor<T>, tangent: &Tensor<T>) -> Tensor<T> {
+        let values = a.values();
+        let tangent = tangent.values();
+        let mut buffer = vec![T::zero(); values.len()];
+        for lane in lanes(&a.shape, self.dim) {
+            let mut before = T::one();
+            for i in 0..lane.len() {
+                let mut partial = before;
+                for j in i..lane.len() {
+                    if j > i {
+                        partial *= values[lane[j]];
+                    }
+                    buffer[lane[j]] += tangent[lane[i]]*partial;
+                }
+                before *= values[lane[i]];
+            }
+        }
+        Tensor::new(buffer, &a.shape)
+    }
+}
+
+impl<T: Float> Function<T> for CumProd {
+    fn name(&self) -> &str {
+        "CumProd"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_index(self.dim, x[0].borrow().ndim())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        scan(&x[0].borrow(), self.dim, false, |acc, x| acc*x)
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        if x[0].borrow().values().iter().any(|value| value.is_zero()) {
+            // the grad of out[j] lines up with the new dimension of the partials
+            let weighted = rc(Mult::forward(self.partials(x[0].clone()), rc(Unsqueeze::forward(grad, self.dim))));
+            return vec![SumDim::forward(weighted, &[self.dim + 1], false)]
+        }
+        // d out[j] / d a[i] = out[j] / a[i] for every j >= i
+        let out = rc(CumProd::forward(x[0].clone(), self.dim));
+        let summed = rc(CumSum::forward(rc(Mult::forward(grad, out)), self.dim, true));
+        vec![Div::forward(summed, x[0].clone())]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        if x[0].borrow().values().iter().any(|value| value.is_zero()) {
+            return self.tangent_partials(&x[0].borrow(), &tangents[0].borrow())
+        }
+        let ratios = rc(Div::forward_nograd(tangents[0].clone(), x[0].clone()));
+        Mult::forward_nograd(rc(out.detach()), rc(CumSum::forward_nograd(ratios, self.dim, false)))
+    }
+}
+
+/// `log(cumsum(exp(a)))` along `dim`, computed without overflowing for large inputs.
+#[derive(Clone)]
+pub struct LogCumSumExp {
+    pub dim: usize
+}
+
+impl LogCumSumExp {
+    pub fn forward_nograd<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        LogCumSumExp {dim}.forward(&[a])
+    }
+
+    pub fn forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Tensor<T> {
+        apply(Rc::new(LogCumSumExp {dim}), vec![a])
+    }
+
+    pub fn try_forward<T: Float>(a: Rc<RefCell<Tensor<T>>>, dim: usize)-> Result<Tensor<T>> {
+        try_apply(Rc::new(LogCumSumExp {dim}), vec![a])
+    }
+
+    /// `log(sign*values)` where that is positive and `-inf` elsewhere, with no grad flowing
+    /// through the positions that are not positive.
+    fn signed_log<T: Float>(values: Rc<RefCell<Tensor<T>>>, sign: f64) -> Rc<RefCell<Tensor<T>>> {
+        let signed = rc(Mult::forward(values, constant(sign)));
+        let skipped = signed.borrow().le(&Tensor::scalar(T::zero()));
+        let safe = rc(MaskedFill::forward(signed, &skipped, constant(1.0)));
+        rc(MaskedFill::forward(rc(Log::forward(safe)), &skipped, constant(f64::NEG_INFINITY)))
+    }
+
+    /// `out` with its `-inf`, which only follow lanes that start with `-inf` elements, replaced
+    /// by 0 so that subtracting it stays finite. Those elements get no grad either way.
+    fn finite<T: Float>(out: Rc<RefCell<Tensor<T>>>) -> Rc<RefCell<Tensor<T>>> {
+        let masked = out.borrow().eq(&Tensor::scalar(T::neg_infinity()));
+        rc(MaskedFill::forward(out, &masked, constant(0.0)))
+    }
+
+    /// Reverses the order along `dim`.
+    fn flip<T: Float>(&self, a: Rc<RefCell<Tensor<T>>>) -> Rc<RefCell<Tensor<T>>> {
+        let reversed: Vec<u32> = (0..a.borrow().shape[self.dim]).rev().collect();
+        rc(IndexSelect::forward(a, self.dim, &reversed))
+    }
+}
+
+impl<T: Float> Function<T> for LogCumSumExp {
+    fn name(&self) -> &str {
+        "LogCumSumExp"
+    }
+
+    fn check(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Result<()> {
+        check_index(self.dim, x[0].borrow().ndim())
+    }
+
+    fn forward(&self, x: &[Rc<RefCell<Tensor<T>>>]) -> Tensor<T> {
+        scan(&x[0].borrow(), self.dim, false, |acc, x| {
+            let (high, low) = if acc > x {(acc, x)} else {(x, acc)};
+            if low == T::neg_infinity() || high == T::infinity() {high} else {high + (low - high).exp().ln_1p()}
+        })
+    }
+
+    fn vjp(&self, grad: Rc<RefCell<Tensor<T>>>, x: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Tensor<T>> {
+        // d out[j] / d a[i] = exp(a[i] - out[j]) for every j >= i. The sum over j is taken in log
+        // space as exp(a[i] + log(sum of grad[j]*exp(-out[j]))), separately for the positive and
+        // the negative grads, so every exponent is at most the log of the grads.
+        let out = LogCumSumExp::finite(rc(LogCumSumExp::forward(x[0].clone(), self.dim)));
+        let [positive, negative] = [1.0, -1.0].map(|sign| {
+            let terms = rc(Sub::forward(LogCumSumExp::signed_log(grad.clone(), sign), out.clone()));
+            let tails = self.flip(rc(LogCumSumExp::forward(self.flip(terms), self.dim)));
+            rc(Exp::forward(rc(Add::forward(x[0].clone(), tails))))
+        });
+        vec![Sub::forward(positive, negative)]
+    }
+
+    fn jvp(&self, tangents: &[Rc<RefCell<Tensor<T>>>], x: &[Rc<RefCell<Tensor<T>>>], out: &Tensor<T>) -> Tensor<T> {
+        // the sum over i <= j of tangent[i]*exp(a[i] - out[j]), in log space like the vjp, from
+        // detached inputs so that no tangents are pushed through these ops in turn
+        let _mode = GradModeGuard::new(false);
+        let a = rc(x[0].borrow().detach());
+        let out = LogCumSumExp::finite(rc(out.detach()));
+        let [positive, negative] = [1.0, -1.0].map(|sign| {
+            let terms = rc(Add::forward(LogCumSumExp::signed_log(rc(tangents[0].borrow().detach()), sign), a.clone()));
+            rc(Exp::forward(rc(Sub::forward(rc(LogCumSumExp::forward(terms, self.dim)), out.clone()))))
+        });
+        Sub::forward(positive, negative)
     }
 }
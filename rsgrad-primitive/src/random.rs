@@ -0,0 +1,236 @@
+use std::cell::RefCell;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use crate::element::Float;
+use crate::error::{RsgradError, Result, or_panic};
+use crate::tensor::{Tensor, numel};
+
+thread_local! {
+    static GLOBAL: RefCell<Generator> = RefCell::new(Generator::from_entropy());
+}
+
+/// A source of random tensors. Two generators built from the same seed draw the same values,
+/// which is what makes initialization and shuffling reproducible.
+pub struct Generator {
+    rng: StdRng
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Generator {
+        Generator {rng: StdRng::seed_from_u64(seed)}
+    }
+
+    /// A generator seeded from the operating system, different on every run.
+    pub fn from_entropy() -> Generator {
+        Generator {rng: StdRng::from_entropy()}
+    }
+
+    /// Draws from [`low`, `high`). Values that round up to `high` in `T` are drawn again.
+    fn sample_uniform<T: Float>(&mut self, low: f64, high: f64) -> T {
+        loop {
+            let value = T::from_f64(low + (high - low)*self.rng.gen::<f64>());
+            if value.into_f64() < high || low == high {
+                return value
+            }
+        }
+    }
+
+    /// A standard normal draw from the Box-Muller transform.
+    fn sample_standard_normal(&mut self) -> f64 {
+        let u1: f64 = 1.0 - self.rng.gen::<f64>();
+        let u2: f64 = self.rng.gen::<f64>();
+        (-2.0*u1.ln()).sqrt() * (2.0*std::f64::consts::PI*u2).cos()
+    }
+
+    /// Uniform values from [`low`, `high`). `low` cannot be above `high`.
+    pub fn uniform<T: Float>(&mut self, low: f64, high: f64, shape: &[u32]) -> Tensor<T> {
+        or_panic(self.try_uniform(low, high, shape))
+    }
+
+    pub fn try_uniform<T: Float>(&mut self, low: f64, high: f64, shape: &[u32]) -> Result<Tensor<T>> {
+        check_bounds("uniform", low, high)?;
+        let data: Vec<T> = (0..numel(shape)).map(|_| self.sample_uniform(low, high)).collect();
+        Tensor::try_new(data, shape)
+    }
+
+    /// Normally distributed values. `std` cannot be negative.
+    pub fn normal<T: Float>(&mut self, mean: f64, std: f64, shape: &[u32]) -> Tensor<T> {
+        or_panic(self.try_normal(mean, std, shape))
+    }
+
+    pub fn try_normal<T: Float>(&mut self, mean: f64, std: f64, shape: &[u32]) -> Result<Tensor<T>> {
+        check_std("normal", std)?;
+        let data: Vec<T> = (0..numel(shape)).map(|_| T::from_f64(mean + std*self.sample_standard_normal())).collect();
+        Tensor::try_new(data, shape)
+    }
+
+    /// Normally distributed values redrawn until they fall into [`low`, `high`], the bounds being
+    /// absolute values rather than multiples of `std`. Bounds far out in a tail make this slow.
+    /// `std` cannot be negative and the bounds have to contain a value. Equal bounds fill the
+    /// result with that value.
+    pub fn truncated_normal<T: Float>(&mut self, mean: f64, std: f64, low: f64, high: f64, shape: &[u32]) -> Tensor<T> {
+        or_panic(self.try_truncated_normal(mean, std, low, high, shape))
+    }
+
+    pub fn try_truncated_normal<T: Float>(&mut self, mean: f64, std: f64, low: f64, high: f64, shape: &[u32]) -> Result<Tensor<T>> {
+        check_std("truncated_normal", std)?;
+        check_bounds("truncated_normal", low, high)?;
+        if std == 0.0 && !(low..=high).contains(&mean) {
+            return Err(RsgradError::invalid("truncated_normal", "bounds exclude the mean with a std of 0"))
+        }
+        // a single point would never be drawn by the rejection loop below
+        if low == high {
+            return Tensor::try_new(vec![T::from_f64(low); numel(shape)], shape)
+        }
+        let data: Vec<T> = (0..numel(shape)).map(|_| loop {
+            let value = mean + std*self.sample_standard_normal();
+            if (low..=high).contains(&value) {
+                return T::from_f64(value)
+            }
+        }).collect();
+        Tensor::try_new(data, shape)
+    }
+
+    /// `true` with the probability held by each element of `probs`, which all have to be in [0, 1].
+    pub fn bernoulli<T: Float>(&mut self, probs: &Tensor<T>) -> Tensor<bool> {
+        or_panic(self.try_bernoulli(probs))
+    }
+
+    pub fn try_bernoulli<T: Float>(&mut self, probs: &Tensor<T>) -> Result<Tensor<bool>> {
+        let data: Vec<bool> = probs.values().iter().map(|&prob| {
+            let prob = prob.into_f64();
+            if !(0.0..=1.0).contains(&prob) {
+                return Err(RsgradError::invalid("bernoulli", &format!("probability {} is not in [0, 1]", prob)))
+            }
+            Ok(self.rng.gen::<f64>() < prob)
+        }).collect::<Result<Vec<bool>>>()?;
+        Ok(Tensor::new(data, &probs.shape))
+    }
+
+    /// Draws `num_samples` category indices from each row of `weights`, a vector or a matrix of
+    /// non-negative, not necessarily normalized, weights. Without `replacement` every category is
+    /// drawn at most once, so a row needs at least `num_samples` nonzero weights.
+    pub fn multinomial<T: Float>(&mut self, weights: &Tensor<T>, num_samples: u32, replacement: bool) -> Tensor<i64> {
+        or_panic(self.try_multinomial(weights, num_samples, replacement))
+    }
+
+    pub fn try_multinomial<T: Float>(&mut self, weights: &Tensor<T>, num_samples: u32, replacement: bool) -> Result<Tensor<i64>> {
+        if weights.ndim() != 1 && weights.ndim() != 2 {
+            return Err(RsgradError::rank("multinomial", 2, weights.ndim()))
+        }
+        let categories = *weights.shape.last().unwrap() as usize;
+        if categories == 0 {
+            return Err(RsgradError::invalid("multinomial", "weights have no categories"))
+        }
+        let values: Vec<f64> = weights.values().iter().map(|&weight| weight.into_f64()).collect();
+        let mut data: Vec<i64> = Vec::new();
+        for row in values.chunks(categories) {
+            if let Some(&weight) = row.iter().find(|&&weight| !(weight >= 0.0 && weight.is_finite())) {
+                return Err(RsgradError::invalid("multinomial", &format!("weight {} is not non-negative and finite", weight)))
+            }
+            let mut row = row.to_vec();
+            let nonzero = row.iter().filter(|&&weight| weight > 0.0).count();
+            if nonzero == 0 || (!replacement && nonzero < num_samples as usize) {
+                return Err(RsgradError::invalid("multinomial", &format!("cannot draw {} samples from {} nonzero weights", num_samples, nonzero)))
+            }
+            for _ in 0..num_samples {
+                let total: f64 = row.iter().sum();
+                let target = total*self.rng.gen::<f64>();
+                let mut cumulative = 0.0;
+                // the last nonzero category also catches a target that rounding pushed past the total
+                let mut category = row.iter().rposition(|&weight| weight > 0.0).unwrap();
+                for (index, &weight) in row.iter().enumerate() {
+                    cumulative += weight;
+                    if weight > 0.0 && target < cumulative {
+                        category = index;
+                        break
+                    }
+                }
+                if !replacement {
+                    row[category] = 0.0;
+                }
+                data.push(category as i64);
+            }
+        }
+        let mut shape = weights.shape.clone();
+        *shape.last_mut().unwrap() = num_samples;
+        Ok(Tensor::new(data, &shape))
+    }
+
+    /// A random ordering of 0..`n`, e.g. for shuffling a dataset.
+    pub fn randperm(&mut self, n: u32) -> Tensor<i64> {
+        let mut data: Vec<i64> = (0..n as i64).collect();
+        data.shuffle(&mut self.rng);
+        Tensor::new(data, &[n])
+    }
+}
+
+fn check_bounds(op: &str, low: f64, high: f64) -> Result<()> {
+    if low <= high {Ok(())} else {Err(RsgradError::invalid(op, &format!("low {} is not at most high {}", low, high)))}
+}
+
+fn check_std(op: &str, std: f64) -> Result<()> {
+    if std >= 0.0 {Ok(())} else {Err(RsgradError::invalid(op, &format!("std {} is negative", std)))}
+}
+
+/// Reseeds the generator behind [`Tensor::rand`], [`Tensor::randn`] and the free functions of
+/// this module for the current thread.
+pub fn manual_seed(seed: u64) {
+    GLOBAL.with(|generator| *generator.borrow_mut() = Generator::new(seed));
+}
+
+/// Runs `f` with the current thread's global generator.
+pub fn with_global_generator<R>(f: impl FnOnce(&mut Generator) -> R) -> R {
+    GLOBAL.with(|generator| f(&mut generator.borrow_mut()))
+}
+
+/// [`Generator::uniform`] on the global generator.
+pub fn uniform<T: Float>(low: f64, high: f64, shape: &[u32]) -> Tensor<T> {
+    with_global_generator(|generator| generator.uniform(low, high, shape))
+}
+
+pub fn try_uniform<T: Float>(low: f64, high: f64, shape: &[u32]) -> Result<Tensor<T>> {
+    with_global_generator(|generator| generator.try_uniform(low, high, shape))
+}
+
+/// [`Generator::normal`] on the global generator.
+pub fn normal<T: Float>(mean: f64, std: f64, shape: &[u32]) -> Tensor<T> {
+    with_global_generator(|generator| generator.normal(mean, std, shape))
+}
+
+pub fn try_normal<T: Float>(mean: f64, std: f64, shape: &[u32]) -> Result<Tensor<T>> {
+    with_global_generator(|generator| generator.try_normal(mean, std, shape))
+}
+
+/// [`Generator::truncated_normal`] on the global generator.
+pub fn truncated_normal<T: Float>(mean: f64, std: f64, low: f64, high: f64, shape: &[u32]) -> Tensor<T> {
+    with_global_generator(|generator| generator.truncated_normal(mean, std, low, high, shape))
+}
+
+pub fn try_truncated_normal<T: Float>(mean: f64, std: f64, low: f64, high: f64, shape: &[u32]) -> Result<Tensor<T>> {
+    with_global_generator(|generator| generator.try_truncated_normal(mean, std, low, high, shape))
+}
+
+/// [`Generator::bernoulli`] on the global generator.
+pub fn bernoulli<T: Float>(probs: &Tensor<T>) -> Tensor<bool> {
+    with_global_generator(|generator| generator.bernoulli(probs))
+}
+
+pub fn try_bernoulli<T: Float>(probs: &Tensor<T>) -> Result<Tensor<bool>> {
+    with_global_generator(|generator| generator.try_bernoulli(probs))
+}
+
+/// [`Generator::multinomial`] on the global generator.
+pub fn multinomial<T: Float>(weights: &Tensor<T>, num_samples: u32, replacement: bool) -> Tensor<i64> {
+    with_global_generator(|generator| generator.multinomial(weights, num_samples, replacement))
+}
+
+pub fn try_multinomial<T: Float>(weights: &Tensor<T>, num_samples: u32, replacement: bool) -> Result<Tensor<i64>> {
+    with_global_generator(|generator| generator.try_multinomial(weights, num_samples, replacement))
+}
+
+/// [`Generator::randperm`] on the global generator.
+pub fn randperm(n: u32) -> Tensor<i64> {
+    with_global_generator(|generator| generator.randperm(n))
+}
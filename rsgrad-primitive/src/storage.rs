@@ -0,0 +1,58 @@
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+/// Reference counted element buffer. Views of a tensor share one `Storage`, so reshaping or
+/// permuting never copies. Writing through a storage that is still shared copies it first,
+/// so a write never shows up in another tensor.
+#[derive(Clone, Default)]
+pub struct Storage<T = f32>(Rc<Vec<T>>);
+
+impl<T> Storage<T> {
+    pub fn new(data: Vec<T>) -> Storage<T> {
+        Storage(Rc::new(data))
+    }
+
+    /// Whether both handles point at the same allocation.
+    pub fn ptr_eq(a: &Storage<T>, b: &Storage<T>) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl<T> Deref for Storage<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Storage<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        Rc::make_mut(&mut self.0)
+    }
+}
+
+impl<T> From<Vec<T>> for Storage<T> {
+    fn from(data: Vec<T>) -> Storage<T> {
+        Storage::new(data)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Storage<T> {
+    fn eq(&self, other: &Storage<T>) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<T: PartialEq> PartialEq<Vec<T>> for Storage<T> {
+    fn eq(&self, other: &Vec<T>) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Storage<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -1,114 +1,768 @@
 
 use std::rc::Rc;
-use crate::ops::Op;
-use crate::ops::Mult;
+use crate::ops::Function;
+use crate::ops::{Add, Where};
 use std::cell::RefCell;
-use rand::prelude::*;
+use std::collections::HashSet;
+use std::borrow::Cow;
+use crate::storage::Storage;
+use crate::grad_mode::{is_grad_enabled, GradModeGuard};
+use crate::error::{RsgradError, Result, or_panic, check_index};
+use crate::element::{DType, Element, Float};
+use crate::nested::NestedData;
+use crate::random;
 
-pub struct Tensor {
-    pub buffer: Vec<f32>,
+pub struct Tensor<T: Element = f32> {
+    pub buffer: Storage<T>,
+    pub shape: Vec<u32>,
     pub stride: Vec<u32>,
-    pub grad: Option<Rc<RefCell<Tensor>>>,
-    pub children: Vec<Rc<RefCell<Tensor>>>,
-    pub op: Option<Op>
+    pub offset: usize,
+    pub grad: Option<Rc<RefCell<Tensor<T>>>>,
+    pub children: Vec<Rc<RefCell<Tensor<T>>>>,
+    pub op: Option<Rc<dyn Function<T>>>,
+    pub tangent: Option<Rc<RefCell<Tensor<T>>>>,
+    pub requires_grad: bool
 }
 
-impl Tensor {
-    pub fn new(data: Vec<f32> , shape: &[u32]) -> Tensor {
-        let ndims: usize = shape.len();
-        let mut stride: Vec<u32> = vec![1; ndims];
-        for idx in (1..ndims).rev() {
-            stride[idx-1] = stride[idx]*shape[idx];
+pub(crate) fn numel(shape: &[u32]) -> usize {
+    shape.iter().map(|&dim| dim as usize).product()
+}
+
+pub(crate) fn contiguous_stride(shape: &[u32]) -> Vec<u32> {
+    let ndims: usize = shape.len();
+    let mut stride: Vec<u32> = vec![1; ndims];
+    for idx in (1..ndims).rev() {
+        stride[idx-1] = stride[idx]*shape[idx];
+    }
+    stride
+}
+
+/// `shape` with every dimension in `dims` reduced to size 1.
+pub(crate) fn reduced_shape(shape: &[u32], dims: &[usize]) -> Vec<u32> {
+    or_panic(try_reduced_shape(shape, dims))
+}
+
+pub(crate) fn try_reduced_shape(shape: &[u32], dims: &[usize]) -> Result<Vec<u32>> {
+    let mut reduced = shape.to_vec();
+    for (position, &dim) in dims.iter().enumerate() {
+        check_index(dim, shape.len())?;
+        if dims[..position].contains(&dim) {
+            return Err(RsgradError::invalid("reduce", &format!("dimension {} is reduced twice", dim)))
         }
-        Tensor {buffer: data, stride: stride, grad: None, children: Vec::new(), op: None}
+        reduced[dim] = 1;
     }
+    Ok(reduced)
+}
+
+/// The multi-dimensional index of the `flat`-th element of `shape` in row-major order.
+pub(crate) fn unravel(mut flat: usize, shape: &[u32]) -> Vec<u32> {
+    let mut index: Vec<u32> = vec![0; shape.len()];
+    for (dim, &size) in shape.iter().enumerate().rev() {
+        index[dim] = (flat % size as usize) as u32;
+        flat /= size as usize;
+    }
+    index
+}
 
-    pub fn rand(shape: &[u32]) -> Tensor {
-        let size: u32 = shape.iter().fold(1, |acc, &x| acc * x);
-        let mut data: Vec<f32> = vec![1.0; usize::try_from(size).unwrap()];
-        let mut rng = rand::thread_rng();
-        for i in 0..size {
-            let ind:usize = usize::try_from(i).unwrap();
-            data[ind] = rng.gen();
+/// The flat row-major indices of every line of elements running along `dim`, in order.
+pub(crate) fn lanes(shape: &[u32], dim: usize) -> Vec<Vec<usize>> {
+    let size = shape[dim] as usize;
+    let inner = numel(&shape[dim+1..]);
+    let outer = numel(&shape[..dim]);
+    (0..outer).flat_map(|o| (0..inner).map(move |i| (0..size).map(|k| (o*size + k)*inner + i).collect())).collect()
+}
+
+/// Whether `value` takes over from `best` as the running maximum or minimum, `better` being the
+/// strict order. A NaN takes over from any number and is never replaced, so NaNs propagate
+/// through max, min and their arg variants the way PyTorch handles them.
+pub(crate) fn beats<T: Element>(value: T, best: T, better: impl Fn(T, T) -> bool) -> bool {
+    let is_nan = |value: T| value.partial_cmp(&value).is_none();
+    !is_nan(best) && (is_nan(value) || better(value, best))
+}
+
+fn strided_offsets(shape: &[u32], stride: &[u32], start: usize) -> Vec<usize> {
+    let size = numel(shape);
+    let ndims = shape.len();
+    let mut offsets: Vec<usize> = Vec::with_capacity(size);
+    let mut index: Vec<u32> = vec![0; ndims];
+    let mut offset = start;
+    for _ in 0..size {
+        offsets.push(offset);
+        for dim in (0..ndims).rev() {
+            index[dim] += 1;
+            offset += stride[dim] as usize;
+            if index[dim] < shape[dim] {
+                break
+            }
+            offset -= (stride[dim]*shape[dim]) as usize;
+            index[dim] = 0;
         }
-        let ndims: usize = shape.len();
-        let mut stride: Vec<u32> = vec![1; ndims];
-        for idx in (1..ndims).rev() {
-            stride[idx-1] = stride[idx]*shape[idx];
+    }
+    offsets
+}
 
+/// The shape two tensors are broadcast to when combined elementwise. Shapes are aligned from
+/// the last dimension and each pair of sizes has to match or contain a 1, like in NumPy.
+pub fn broadcast_shape(a: &[u32], b: &[u32]) -> Vec<u32> {
+    or_panic(try_broadcast_shape(a, b))
+}
+
+pub fn try_broadcast_shape(a: &[u32], b: &[u32]) -> Result<Vec<u32>> {
+    let ndims = a.len().max(b.len());
+    let mut shape: Vec<u32> = vec![1; ndims];
+    for (dim, size) in shape.iter_mut().enumerate() {
+        let a_size = if dim + a.len() >= ndims {a[dim + a.len() - ndims]} else {1};
+        let b_size = if dim + b.len() >= ndims {b[dim + b.len() - ndims]} else {1};
+        if !(a_size == b_size || a_size == 1 || b_size == 1) {
+            return Err(RsgradError::shape("broadcast", a, b))
         }
-        Tensor {buffer: data, stride: stride, grad: None, children: Vec::new(), op: None}
+        *size = if a_size == 1 {b_size} else {a_size};
     }
+    Ok(shape)
+}
 
-    pub fn constant_fill(constant: f32, shape: &[u32]) -> Tensor {
-        let size: u32 = shape.iter().fold(1, |acc, &x| acc * x);
-        let mut data: Vec<f32> = vec![constant; usize::try_from(size).unwrap()];
-        let ndims: usize = shape.len();
-        let mut stride: Vec<u32> = vec![1; ndims];
-        for idx in (1..ndims).rev() {
-            stride[idx-1] = stride[idx]*shape[idx];
+/// Strides that read a layout of `shape` and `stride` as if it had been broadcast to `target`.
+pub(crate) fn try_broadcast_stride(shape: &[u32], stride: &[u32], target: &[u32]) -> Result<Vec<u32>> {
+    if target.len() < shape.len() {
+        return Err(RsgradError::shape("expand", shape, target))
+    }
+    let lead = target.len() - shape.len();
+    let mut result: Vec<u32> = vec![0; target.len()];
+    for (dim, (&size, &s)) in shape.iter().zip(stride.iter()).enumerate() {
+        if size != target[lead+dim] && size != 1 {
+            return Err(RsgradError::shape("expand", shape, target))
         }
-        Tensor {buffer: data, stride: stride, grad: None, children: Vec::new(), op: None}
+        result[lead+dim] = if size == target[lead+dim] {s} else {0};
+    }
+    Ok(result)
+}
+
+/// Generates an elementwise comparison returning a bool mask, along with its `try_` variant.
+macro_rules! comparison {
+    ($name:ident, $try_name:ident, $op:tt, $doc:literal) => {
+        #[doc = $doc]
+        pub fn $name(&self, other: &Tensor<T>) -> Tensor<bool> {
+            or_panic(self.$try_name(other))
+        }
+
+        pub fn $try_name(&self, other: &Tensor<T>) -> Result<Tensor<bool>> {
+            self.try_zip_with(other, |a, b| a $op b)
+        }
+    };
+}
+
+impl<T: Element> Tensor<T> {
+    pub fn new(data: Vec<T> , shape: &[u32]) -> Tensor<T> {
+        or_panic(Tensor::try_new(data, shape))
+    }
+
+    /// Fails when `data` does not hold exactly as many values as `shape` has elements.
+    pub fn try_new(data: Vec<T>, shape: &[u32]) -> Result<Tensor<T>> {
+        if data.len() != numel(shape) {
+            return Err(RsgradError::shape("new", &[data.len() as u32], shape))
+        }
+        Ok(Tensor {buffer: Storage::new(data), shape: shape.to_vec(), stride: contiguous_stride(shape), offset: 0, grad: None, children: Vec::new(), op: None, tangent: None, requires_grad: true})
+    }
+
+    /// A 0-d tensor holding a single value.
+    pub fn scalar(value: T) -> Tensor<T> {
+        Tensor::new(vec![value], &[])
+    }
+
+    pub fn constant_fill(constant: T, shape: &[u32]) -> Tensor<T> {
+        Tensor::new(vec![constant; numel(shape)], shape)
+    }
+
+    pub fn zeros(shape: &[u32]) -> Tensor<T> {
+        Tensor::constant_fill(T::from_f64(0.0), shape)
+    }
+
+    pub fn ones(shape: &[u32]) -> Tensor<T> {
+        Tensor::constant_fill(T::from_f64(1.0), shape)
+    }
+
+    /// A zero leaf with the shape of `self`.
+    pub fn zeros_like(&self) -> Tensor<T> {
+        Tensor::zeros(&self.shape)
+    }
+
+    /// A leaf with the shape of `self` holding `value` everywhere.
+    pub fn full_like(&self, value: T) -> Tensor<T> {
+        Tensor::constant_fill(value, &self.shape)
+    }
+
+    /// The values from `start` up to, not including, `end` spaced `step` apart, like Python's
+    /// `range`. `step` cannot be 0.
+    pub fn arange(start: T, end: T, step: T) -> Tensor<T> {
+        or_panic(Tensor::try_arange(start, end, step))
+    }
+
+    pub fn try_arange(start: T, end: T, step: T) -> Result<Tensor<T>> {
+        let (start, end, step) = (start.into_f64(), end.into_f64(), step.into_f64());
+        if step == 0.0 || step.is_nan() {
+            return Err(RsgradError::invalid("arange", &format!("step cannot be {}", step)))
+        }
+        let size = ((end - start)/step).ceil().max(0.0) as usize;
+        let data: Vec<T> = (0..size).map(|idx| T::from_f64(start + idx as f64*step)).collect();
+        Tensor::try_new(data, &[size as u32])
+    }
+
+    /// `steps` evenly spaced values from `start` to `end`, both included.
+    pub fn linspace(start: T, end: T, steps: u32) -> Tensor<T> {
+        let (start, end) = (start.into_f64(), end.into_f64());
+        let spacing = if steps > 1 {(end - start)/(steps - 1) as f64} else {0.0};
+        let data: Vec<T> = (0..steps).map(|idx| {
+            if idx + 1 == steps && steps > 1 {T::from_f64(end)} else {T::from_f64(start + idx as f64*spacing)}
+        }).collect();
+        Tensor::new(data, &[steps])
+    }
+
+    /// The `n` by `n` identity matrix.
+    pub fn eye(n: u32) -> Tensor<T> {
+        let mut result = Tensor::zeros(&[n, n]);
+        for idx in 0..n {
+            *result.at(&[idx, idx]) = T::from_f64(1.0);
+        }
+        result
+    }
+
+    /// Reads nested `Vec`s or arrays, inferring the shape from the nesting, so
+    /// `Tensor::from_nested(vec![[1.0, 2.0], [3.0, 4.0]])` has shape `[2, 2]`. Panics on ragged
+    /// input.
+    pub fn from_nested<N: NestedData<Element = T>>(data: N) -> Tensor<T> {
+        or_panic(Tensor::try_from_nested(data))
+    }
+
+    /// Fails when the entries of a level do not all have the same shape.
+    pub fn try_from_nested<N: NestedData<Element = T>>(data: N) -> Result<Tensor<T>> {
+        let shape = data.nested_shape();
+        let mut buffer: Vec<T> = Vec::with_capacity(numel(&shape));
+        data.flatten_into(&shape, &mut buffer)?;
+        Tensor::try_new(buffer, &shape)
+    }
+
+    /// Marks whether backward should compute a grad for this tensor. Leaves require grad by
+    /// default; inputs and targets that are never optimised can opt out.
+    pub fn with_requires_grad(mut self, requires_grad: bool) -> Tensor<T> {
+        self.requires_grad = requires_grad;
+        self
+    }
+
+    /// A new leaf over the same values that is cut off from the graph and requires no grad. The
+    /// storage is shared with `self` until either of them is first written to.
+    pub fn detach(&self) -> Tensor<T> {
+        self.view(self.shape.clone(), self.stride.clone(), self.offset).with_requires_grad(false)
     }
 
     pub fn shape(&self) -> Vec<u32> {
-        let ndims = self.stride.len();
-        let buffer_size = self.buffer.len();
-        let mut shape: Vec<u32> = vec![1; ndims];
-        shape[0] = u32::try_from(buffer_size).unwrap()/self.stride[0];
-        for idx in 1..ndims {
-            shape[idx] = self.stride[idx-1]/self.stride[idx];
+        self.shape.clone()
+    }
+
+    pub fn ndim(&self) -> usize {
+        self.shape.len()
+    }
+
+    pub fn numel(&self) -> usize {
+        numel(&self.shape)
+    }
+
+    /// Whether the elements sit in row-major order in one unbroken run of `buffer`.
+    pub fn is_contiguous(&self) -> bool {
+        let expected = contiguous_stride(&self.shape);
+        self.shape.iter().zip(self.stride.iter().zip(expected.iter())).all(|(&dim, (&s, &e))| dim == 1 || s == e)
+    }
+
+    /// Buffer positions of all elements in row-major logical order.
+    pub fn offsets(&self) -> Vec<usize> {
+        strided_offsets(&self.shape, &self.stride, self.offset)
+    }
+
+    /// Strides that read this tensor as if it had been broadcast to `shape`: missing leading
+    /// dimensions and dimensions of size 1 that get repeated are given a stride of 0.
+    pub fn broadcast_stride(&self, shape: &[u32]) -> Vec<u32> {
+        or_panic(try_broadcast_stride(&self.shape, &self.stride, shape))
+    }
+
+    /// Buffer positions of the elements of this tensor broadcast to `shape`, in row-major order.
+    pub fn broadcast_offsets(&self, shape: &[u32]) -> Vec<usize> {
+        strided_offsets(shape, &self.broadcast_stride(shape), self.offset)
+    }
+
+    /// The elements in row-major logical order, borrowed straight from `buffer` when the
+    /// layout allows it.
+    pub fn values(&self) -> Cow<'_, [T]> {
+        if self.is_contiguous() {
+            Cow::Borrowed(&self.buffer[self.offset..self.offset+self.numel()])
+        } else {
+            Cow::Owned(self.offsets().into_iter().map(|offset| self.buffer[offset]).collect())
+        }
+    }
+
+    /// Copies the values into a fresh row-major buffer, without any graph information.
+    pub fn contiguous(&self) -> Tensor<T> {
+        Tensor::new(self.values().into_owned(), &self.shape)
+    }
+
+    /// The value of a tensor holding a single element.
+    pub fn item(&self) -> T {
+        or_panic(self.try_item())
+    }
+
+    pub fn try_item(&self) -> Result<T> {
+        if self.numel() != 1 {
+            return Err(RsgradError::shape("item", &self.shape, &[]))
+        }
+        Ok(self.values()[0])
+    }
+
+    /// Position of the largest element along `dim`, the first one on ties or the first NaN if
+    /// there is one. Indices are returned as an `i64` tensor.
+    pub fn argmax(&self, dim: usize, keepdim: bool) -> Tensor<i64> {
+        or_panic(self.try_argmax(dim, keepdim))
+    }
+
+    pub fn try_argmax(&self, dim: usize, keepdim: bool) -> Result<Tensor<i64>> {
+        self.arg_extreme(dim, keepdim, |value, best| value > best)
+    }
+
+    /// Position of the smallest element along `dim`, the first one on ties or the first NaN if
+    /// there is one.
+    pub fn argmin(&self, dim: usize, keepdim: bool) -> Tensor<i64> {
+        or_panic(self.try_argmin(dim, keepdim))
+    }
+
+    pub fn try_argmin(&self, dim: usize, keepdim: bool) -> Result<Tensor<i64>> {
+        self.arg_extreme(dim, keepdim, |value, best| value < best)
+    }
+
+    /// Positions that order the elements along `dim`, ascending unless `descending`. Equal
+    /// elements keep their order and NaNs count as larger than everything else.
+    pub fn argsort(&self, dim: usize, descending: bool) -> Tensor<i64> {
+        or_panic(self.try_argsort(dim, descending))
+    }
+
+    pub fn try_argsort(&self, dim: usize, descending: bool) -> Result<Tensor<i64>> {
+        check_index(dim, self.ndim())?;
+        let values = self.values();
+        let is_nan = |value: T| value.partial_cmp(&value).is_none();
+        let compare = |a: T, b: T| a.partial_cmp(&b).unwrap_or_else(|| is_nan(a).cmp(&is_nan(b)));
+        let mut result: Vec<i64> = vec![0; values.len()];
+        for lane in lanes(&self.shape, dim) {
+            let mut order: Vec<usize> = (0..lane.len()).collect();
+            order.sort_by(|&p, &q| {
+                let ordering = compare(values[lane[p]], values[lane[q]]);
+                if descending {ordering.reverse()} else {ordering}
+            });
+            for (&target, &position) in lane.iter().zip(order.iter()) {
+                result[target] = position as i64;
+            }
         }
-        shape
+        Ok(Tensor::new(result, &self.shape).with_requires_grad(false))
     }
 
-    pub fn at_im(&self, index: &[u32]) -> &f32 {
-        assert_eq!(index.len(), self.stride.len());
-        let ndims: usize = index.len();
-        let mut offset: usize = 0;
-        for idx in 0..ndims {
-            offset += usize::try_from(index[idx]*self.stride[idx]).unwrap();
+    fn arg_extreme(&self, dim: usize, keepdim: bool, better: impl Fn(T, T) -> bool) -> Result<Tensor<i64>> {
+        let reduced = try_reduced_shape(&self.shape, &[dim])?;
+        check_index(0, self.shape[dim] as usize)?;
+        let mut best: Vec<Option<T>> = vec![None; numel(&reduced)];
+        let mut result: Tensor<i64> = Tensor::constant_fill(0, &reduced);
+        let targets = result.broadcast_offsets(&self.shape);
+        for (flat, (target, &value)) in targets.into_iter().zip(self.values().iter()).enumerate() {
+            if best[target].is_none_or(|current| beats(value, current, &better)) {
+                best[target] = Some(value);
+                result.buffer[target] = unravel(flat, &self.shape)[dim] as i64;
+            }
+        }
+        Ok(if keepdim {result} else {result.squeeze(dim).contiguous()})
+    }
+
+    pub(crate) fn offset_of(&self, index: &[u32]) -> usize {
+        assert_eq!(index.len(), self.ndim());
+        let mut offset = self.offset;
+        for ((&i, &dim), &s) in index.iter().zip(self.shape.iter()).zip(self.stride.iter()) {
+            assert!(i < dim, "index {} out of range for dimension of size {}", i, dim);
+            offset += (i*s) as usize;
         }
-        &self.buffer[offset]
+        offset
     }
 
-    pub fn at(&mut self, index: &[u32]) -> &mut f32 {
-        assert_eq!(index.len(), self.stride.len());
-        let ndims: usize = index.len();
-        let mut offset: usize = 0;
-        for idx in 0..ndims {
-            offset += usize::try_from(index[idx]*self.stride[idx]).unwrap();
+    fn try_offset_of(&self, index: &[u32]) -> Result<usize> {
+        if index.len() != self.ndim() {
+            return Err(RsgradError::rank("at", self.ndim(), index.len()))
+        }
+        for (&i, &dim) in index.iter().zip(self.shape.iter()) {
+            check_index(i as usize, dim as usize)?;
         }
+        Ok(self.offset_of(index))
+    }
+
+    pub fn at_im(&self, index: &[u32]) -> &T {
+        &self.buffer[self.offset_of(index)]
+    }
+
+    pub fn try_at_im(&self, index: &[u32]) -> Result<&T> {
+        Ok(&self.buffer[self.try_offset_of(index)?])
+    }
+
+    pub fn at(&mut self, index: &[u32]) -> &mut T {
+        let offset = self.offset_of(index);
         &mut self.buffer[offset]
     }
 
-    pub fn clone(&self) -> Tensor {
-        Tensor {buffer: self.buffer.clone(), stride: self.stride.clone(), grad: self.grad.clone(), children: self.children.clone(), op: self.op.clone()}
+    pub fn try_at(&mut self, index: &[u32]) -> Result<&mut T> {
+        let offset = self.try_offset_of(index)?;
+        Ok(&mut self.buffer[offset])
+    }
+
+    /// The grad backward left on this tensor.
+    pub fn try_grad(&self) -> Result<Rc<RefCell<Tensor<T>>>> {
+        self.grad.clone().ok_or(RsgradError::MissingGradient)
+    }
+
+    /// A new leaf over the same storage with the given layout.
+    fn view(&self, shape: Vec<u32>, stride: Vec<u32>, offset: usize) -> Tensor<T> {
+        Tensor {buffer: self.buffer.clone(), shape, stride, offset, grad: None, children: Vec::new(), op: None, tangent: None, requires_grad: true}
+    }
+
+    /// Same elements under a new shape. Only copies when the layout is not contiguous.
+    pub fn reshape(&self, shape: &[u32]) -> Tensor<T> {
+        or_panic(self.try_reshape(shape))
+    }
+
+    pub fn try_reshape(&self, shape: &[u32]) -> Result<Tensor<T>> {
+        if numel(shape) != self.numel() {
+            return Err(RsgradError::shape("reshape", &self.shape, shape))
+        }
+        if !self.is_contiguous() {
+            return self.contiguous().try_reshape(shape)
+        }
+        Ok(self.view(shape.to_vec(), contiguous_stride(shape), self.offset))
+    }
+
+    /// Reorders the dimensions, dimension `i` of the result is dimension `dims[i]` of `self`.
+    pub fn permute(&self, dims: &[usize]) -> Tensor<T> {
+        or_panic(self.try_permute(dims))
+    }
+
+    /// Fails unless `dims` names every dimension exactly once.
+    pub fn try_permute(&self, dims: &[usize]) -> Result<Tensor<T>> {
+        if dims.len() != self.ndim() {
+            return Err(RsgradError::rank("permute", self.ndim(), dims.len()))
+        }
+        let mut seen: Vec<bool> = vec![false; self.ndim()];
+        for &dim in dims {
+            check_index(dim, self.ndim())?;
+            if seen[dim] {
+                return Err(RsgradError::shape("permute", &self.shape, &dims.iter().map(|&dim| dim as u32).collect::<Vec<u32>>()))
+            }
+            seen[dim] = true;
+        }
+        let shape = dims.iter().map(|&dim| self.shape[dim]).collect();
+        let stride = dims.iter().map(|&dim| self.stride[dim]).collect();
+        Ok(self.view(shape, stride, self.offset))
+    }
+
+    /// Swaps the last two dimensions.
+    pub fn transpose(&self) -> Tensor<T> {
+        or_panic(self.try_transpose())
+    }
+
+    pub fn try_transpose(&self) -> Result<Tensor<T>> {
+        if self.ndim() < 2 {
+            return Err(RsgradError::rank("transpose", 2, self.ndim()))
+        }
+        let mut dims: Vec<usize> = (0..self.ndim()).collect();
+        dims.swap(self.ndim()-2, self.ndim()-1);
+        self.try_permute(&dims)
+    }
+
+    /// Every `step`-th element of dimension `dim` from `start` up to, not including, `end`.
+    /// `step` has to be positive.
+    pub fn slice(&self, dim: usize, start: u32, end: u32, step: u32) -> Tensor<T> {
+        or_panic(self.try_slice(dim, start, end, step))
+    }
+
+    pub fn try_slice(&self, dim: usize, start: u32, end: u32, step: u32) -> Result<Tensor<T>> {
+        if step == 0 {
+            return Err(RsgradError::invalid("slice", "step has to be positive"))
+        }
+        check_index(dim, self.ndim())?;
+        check_index(end as usize, self.shape[dim] as usize + 1)?;
+        check_index(start as usize, end as usize + 1)?;
+        let mut shape = self.shape.clone();
+        let mut stride = self.stride.clone();
+        shape[dim] = (end - start).div_ceil(step);
+        stride[dim] *= step;
+        Ok(self.view(shape, stride, self.offset + (start*self.stride[dim]) as usize))
+    }
+
+    /// Broadcasts to `shape` by giving repeated dimensions a stride of 0.
+    pub fn expand(&self, shape: &[u32]) -> Tensor<T> {
+        or_panic(self.try_expand(shape))
+    }
+
+    pub fn try_expand(&self, shape: &[u32]) -> Result<Tensor<T>> {
+        Ok(self.view(shape.to_vec(), try_broadcast_stride(&self.shape, &self.stride, shape)?, self.offset))
+    }
+
+    /// Removes dimension `dim`, which has to be of size 1.
+    pub fn squeeze(&self, dim: usize) -> Tensor<T> {
+        or_panic(self.try_squeeze(dim))
+    }
+
+    pub fn try_squeeze(&self, dim: usize) -> Result<Tensor<T>> {
+        check_index(dim, self.ndim())?;
+        if self.shape[dim] != 1 {
+            return Err(RsgradError::shape("squeeze", &self.shape[dim..dim+1], &[1]))
+        }
+        let mut shape = self.shape.clone();
+        let mut stride = self.stride.clone();
+        shape.remove(dim);
+        stride.remove(dim);
+        Ok(self.view(shape, stride, self.offset))
+    }
+
+    /// Inserts a dimension of size 1 at position `dim`.
+    pub fn unsqueeze(&self, dim: usize) -> Tensor<T> {
+        or_panic(self.try_unsqueeze(dim))
+    }
+
+    pub fn try_unsqueeze(&self, dim: usize) -> Result<Tensor<T>> {
+        check_index(dim, self.ndim() + 1)?;
+        let inner = if dim < self.ndim() {self.stride[dim]*self.shape[dim]} else {1};
+        let mut shape = self.shape.clone();
+        let mut stride = self.stride.clone();
+        shape.insert(dim, 1);
+        stride.insert(dim, inner);
+        Ok(self.view(shape, stride, self.offset))
+    }
+
+    pub fn dtype(&self) -> DType {
+        T::DTYPE
+    }
+
+    /// Converts every element to `U` like an `as` cast, truncating towards zero for integer
+    /// types. Casts are not differentiable, the result is a new leaf that requires no grad.
+    pub fn cast<U: Element>(&self) -> Tensor<U> {
+        let data: Vec<U> = self.values().iter().map(|&value| U::from_f64(value.into_f64())).collect();
+        Tensor::new(data, &self.shape).with_requires_grad(false)
+    }
+
+    /// Like [`Tensor::cast`], but fails instead of changing a value: integer and bool targets
+    /// only take whole numbers they can represent, float targets do not overflow to infinity.
+    pub fn try_cast<U: Element>(&self) -> Result<Tensor<U>> {
+        let data: Vec<U> = self.values().iter().map(|&value| {
+            let value = value.into_f64();
+            let converted = U::from_f64(value);
+            let exact = if U::DTYPE.is_float() {
+                !value.is_finite() || converted.into_f64().is_finite()
+            } else {
+                converted.into_f64() == value
+            };
+            if exact {Ok(converted)} else {Err(RsgradError::DtypeMismatch {expected: U::DTYPE.to_string(), got: value.to_string()})}
+        }).collect::<Result<Vec<U>>>()?;
+        Ok(Tensor::new(data, &self.shape).with_requires_grad(false))
+    }
+
+    /// Combines `self` and `other` elementwise after broadcasting them to a common shape. The
+    /// result is a leaf that requires no grad.
+    pub(crate) fn try_zip_with<U: Element, R: Element>(&self, other: &Tensor<U>, f: impl Fn(T, U) -> R) -> Result<Tensor<R>> {
+        let shape = try_broadcast_shape(&self.shape, &other.shape)?;
+        let lhs = self.broadcast_offsets(&shape);
+        let rhs = other.broadcast_offsets(&shape);
+        let data: Vec<R> = lhs.iter().zip(rhs.iter()).map(|(&i, &j)| f(self.buffer[i], other.buffer[j])).collect();
+        Ok(Tensor::new(data, &shape).with_requires_grad(false))
     }
 
-    pub fn backward(&mut self, gradient: Rc<RefCell<Tensor>>) {
+    comparison!(eq, try_eq, ==, "Whether the elements of `self` and `other` are equal, after broadcasting.");
+    comparison!(ne, try_ne, !=, "Whether the elements of `self` and `other` differ, after broadcasting.");
+    comparison!(lt, try_lt, <, "Whether the elements of `self` are less than those of `other`, after broadcasting.");
+    comparison!(le, try_le, <=, "Whether the elements of `self` are at most those of `other`, after broadcasting.");
+    comparison!(gt, try_gt, >, "Whether the elements of `self` are greater than those of `other`, after broadcasting.");
+    comparison!(ge, try_ge, >=, "Whether the elements of `self` are at least those of `other`, after broadcasting.");
+}
+
+impl Tensor<bool> {
+    pub fn logical_and(&self, other: &Tensor<bool>) -> Tensor<bool> {
+        or_panic(self.try_logical_and(other))
+    }
+
+    pub fn try_logical_and(&self, other: &Tensor<bool>) -> Result<Tensor<bool>> {
+        self.try_zip_with(other, |a, b| a && b)
+    }
+
+    pub fn logical_or(&self, other: &Tensor<bool>) -> Tensor<bool> {
+        or_panic(self.try_logical_or(other))
+    }
+
+    pub fn try_logical_or(&self, other: &Tensor<bool>) -> Result<Tensor<bool>> {
+        self.try_zip_with(other, |a, b| a || b)
+    }
+
+    pub fn logical_xor(&self, other: &Tensor<bool>) -> Tensor<bool> {
+        or_panic(self.try_logical_xor(other))
+    }
+
+    pub fn try_logical_xor(&self, other: &Tensor<bool>) -> Result<Tensor<bool>> {
+        self.try_zip_with(other, |a, b| a != b)
+    }
+
+    pub fn logical_not(&self) -> Tensor<bool> {
+        Tensor::new(self.values().iter().map(|&value| !value).collect(), &self.shape).with_requires_grad(false)
+    }
+
+    /// Picks the element of `on_true` where this mask is set and of `on_false` elsewhere, all
+    /// three broadcast to a common shape. Differentiable in both value arguments, see
+    /// [`Where`](crate::ops::Where).
+    pub fn where_cond<T: Float>(&self, on_true: Rc<RefCell<Tensor<T>>>, on_false: Rc<RefCell<Tensor<T>>>) -> Tensor<T> {
+        Where::forward(self, on_true, on_false)
+    }
+
+    pub fn try_where_cond<T: Float>(&self, on_true: Rc<RefCell<Tensor<T>>>, on_false: Rc<RefCell<Tensor<T>>>) -> Result<Tensor<T>> {
+        Where::try_forward(self, on_true, on_false)
+    }
+}
+
+impl<T: Float> Tensor<T> {
+    /// Uniform values from [0, 1), drawn from the thread's global generator so that
+    /// [`random::manual_seed`] makes them reproducible.
+    pub fn rand(shape: &[u32]) -> Tensor<T> {
+        random::uniform(0.0, 1.0, shape)
+    }
+
+    /// Standard normal values from the thread's global generator.
+    pub fn randn(shape: &[u32]) -> Tensor<T> {
+        random::normal(0.0, 1.0, shape)
+    }
+
+    /// Attaches `op` and its `children` to a freshly computed result. Nothing is recorded when
+    /// grad mode is off or none of the children requires grad. When any of the children carries
+    /// a tangent, the op's jacobian-vector product is pushed forward into the result too.
+    pub(crate) fn record(mut self, op: Rc<dyn Function<T>>, children: Vec<Rc<RefCell<Tensor<T>>>>) -> Tensor<T> {
+        if children.iter().any(|child| child.borrow().tangent.is_some()) {
+            let tangents: Vec<Rc<RefCell<Tensor<T>>>> = children.iter().map(|child| {
+                let child = child.borrow();
+                child.tangent.clone().unwrap_or_else(|| Rc::new(RefCell::new(Tensor::constant_fill(T::zero(), &child.shape()))))
+            }).collect();
+            self.tangent = Some(Rc::new(RefCell::new(op.jvp(&tangents, &children, &self))));
+        }
+        self.requires_grad = is_grad_enabled() && children.iter().any(|child| child.borrow().requires_grad);
+        if !self.requires_grad {
+            return self
+        }
+        self.children = children;
+        self.op = Some(op);
+        self
+    }
+
+    pub fn backward(&mut self, gradient: Rc<RefCell<Tensor<T>>>) {
+        self.backward_with(gradient, false);
+    }
+
+    /// Like [`Tensor::backward_with`], but fails when `gradient` does not have the shape of
+    /// this tensor instead of producing grads of the wrong shape.
+    pub fn try_backward_with(&mut self, gradient: Rc<RefCell<Tensor<T>>>, create_graph: bool) -> Result<()> {
+        if gradient.borrow().shape != self.shape {
+            return Err(RsgradError::shape("backward", &self.shape, &gradient.borrow().shape))
+        }
+        self.backward_with(gradient, create_graph);
+        Ok(())
+    }
+
+    /// Backpropagates `gradient` from this tensor. With `create_graph` the vector-Jacobian
+    /// products are recorded as graph nodes themselves, so the resulting grads can be
+    /// differentiated again (second derivatives, gradient penalties, Hessian-vector products).
+    pub fn backward_with(&mut self, gradient: Rc<RefCell<Tensor<T>>>, create_graph: bool) {
         self.grad = Some(gradient);
         if self.op.is_none() {
-            return ()
+            return
+        }
+        // vjps only become graph nodes themselves when a differentiable grad was asked for
+        let _mode = GradModeGuard::new(create_graph);
+        let order = Tensor::topological_order(&self.children);
+        // grads of intermediate nodes are rebuilt from scratch on every pass, only leaves accumulate
+        for node in &order {
+            let mut tensor = node.borrow_mut();
+            if tensor.op.is_some() {
+                tensor.grad = None;
+            }
+        }
+        let grads: Vec<Tensor<T>> = self.op.as_ref().unwrap().vjp(self.grad.clone().unwrap(), &self.children);
+        Tensor::accumulate_grads(&self.children, grads, create_graph);
+        for node in &order {
+            let (op, grad, children) = {
+                let tensor = node.borrow();
+                (tensor.op.clone(), tensor.grad.clone(), tensor.children.clone())
+            };
+            if let (Some(op), Some(grad)) = (op, grad) {
+                let grads = op.vjp(grad, &children);
+                Tensor::accumulate_grads(&children, grads, create_graph);
+            }
+        }
+    }
+
+    /// Returns every node reachable from `roots` exactly once, ordered so that a node always
+    /// comes before its children. The traversal keeps its own stack, so graph depth is only
+    /// bounded by memory.
+    fn topological_order(roots: &[Rc<RefCell<Tensor<T>>>]) -> Vec<Rc<RefCell<Tensor<T>>>> {
+        let mut visited: HashSet<*const RefCell<Tensor<T>>> = HashSet::new();
+        let mut order: Vec<Rc<RefCell<Tensor<T>>>> = Vec::new();
+        // (node, children_done) pairs, a node is emitted once all of its children have been
+        let mut stack: Vec<(Rc<RefCell<Tensor<T>>>, bool)> = roots.iter().rev().map(|root| (root.clone(), false)).collect();
+        while let Some((node, children_done)) = stack.pop() {
+            if children_done {
+                order.push(node);
+                continue
+            }
+            if !visited.insert(Rc::as_ptr(&node)) {
+                continue
+            }
+            stack.push((node.clone(), true));
+            for child in node.borrow().children.iter().rev() {
+                if !visited.contains(&Rc::as_ptr(child)) {
+                    stack.push((child.clone(), false));
+                }
+            }
         }
-        let grads: Vec<Tensor> = self.op.as_ref().unwrap().fetch_vjp(self.grad.as_ref().unwrap().clone(), &self.children);
-        let nchild = grads.len();
-        for idx in 0..nchild {
-            let child_grad: Rc<RefCell<Tensor>> = Rc::new(RefCell::new(grads[idx].clone()));
-            self.children[idx].borrow_mut().backward(child_grad);
+        order.reverse();
+        order
+    }
+
+    fn accumulate_grads(nodes: &[Rc<RefCell<Tensor<T>>>], grads: Vec<Tensor<T>>, create_graph: bool) {
+        for (node, mut grad) in nodes.iter().zip(grads) {
+            if !node.borrow().requires_grad {
+                continue
+            }
+            // views into a larger grad would keep all of it alive, so plain grads get their own buffer
+            if !create_graph && (!grad.is_contiguous() || grad.offset != 0 || grad.buffer.len() != grad.numel()) {
+                grad = grad.contiguous();
+            }
+            grad.tangent = None;
+            let grad = Rc::new(RefCell::new(grad));
+            let mut tensor = node.borrow_mut();
+            tensor.grad = Some(match tensor.grad.take() {
+                Some(previous) if create_graph => Rc::new(RefCell::new(Add::forward(previous, grad))),
+                Some(previous) => Rc::new(RefCell::new(Add::forward_nograd(previous, grad))),
+                None => grad
+            });
         }
+    }
+}
 
+impl<T: Element> Clone for Tensor<T> {
+    fn clone(&self) -> Tensor<T> {
+        Tensor {buffer: self.buffer.clone(), shape: self.shape.clone(), stride: self.stride.clone(), offset: self.offset, grad: self.grad.clone(), children: self.children.clone(), op: self.op.clone(), tangent: self.tangent.clone(), requires_grad: self.requires_grad}
     }
+}
 
-    pub fn transpose(&self) -> Tensor {
-        assert_eq!(self.stride.len(), 2);
-        let shape = self.shape();
-        let mut result: Tensor = Tensor::new(self.buffer.clone(), &[shape[1], shape[0]]);
-        for i in 0..shape[1] {
-            for j in 0..shape[0] {
-                *result.at(&[i, j]) = *self.at_im(&[j, i]);
+impl<T: Element> Drop for Tensor<T> {
+    // The default drop glue recurses once per graph level and overflows the stack on long
+    // chains, so uniquely owned children are unlinked here with an explicit work list.
+    fn drop(&mut self) {
+        let mut pending: Vec<Rc<RefCell<Tensor<T>>>> = std::mem::take(&mut self.children);
+        while let Some(node) = pending.pop() {
+            if let Ok(cell) = Rc::try_unwrap(node) {
+                pending.append(&mut cell.into_inner().children);
             }
         }
-        result
     }
 }
@@ -0,0 +1,370 @@
+use crate::tensor::Tensor;
+use crate::ops;
+use crate::einsum;
+use crate::error::{RsgradError, Result};
+use crate::element::Float;
+use std::rc::Rc;
+use std::cell::{Ref, RefCell, RefMut};
+
+/// A cheap to clone handle on a graph node. It wraps the `Rc<RefCell<Tensor<T>>>` the ops work
+/// with, overloads the arithmetic operators and offers the other ops as methods, so
+/// `(x.matmul(&w) + &b).relu().sum()` builds the same graph as the nested `forward` calls.
+#[derive(Clone)]
+pub struct Var<T: Float = f32>(pub Rc<RefCell<Tensor<T>>>);
+
+impl<T: Float> Var<T> {
+    pub fn new(tensor: Tensor<T>) -> Var<T> {
+        Var(Rc::new(RefCell::new(tensor)))
+    }
+
+    /// A 0-d constant that requires no grad.
+    pub fn scalar(value: T) -> Var<T> {
+        Var::new(Tensor::scalar(value).with_requires_grad(false))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, Tensor<T>> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, Tensor<T>> {
+        self.0.borrow_mut()
+    }
+
+    pub fn shape(&self) -> Vec<u32> {
+        self.0.borrow().shape()
+    }
+
+    pub fn item(&self) -> T {
+        self.0.borrow().item()
+    }
+
+    /// The elements in row-major order.
+    pub fn to_vec(&self) -> Vec<T> {
+        self.0.borrow().values().into_owned()
+    }
+
+    pub fn grad(&self) -> Option<Var<T>> {
+        self.0.borrow().grad.clone().map(Var)
+    }
+
+    pub fn try_grad(&self) -> Result<Var<T>> {
+        self.grad().ok_or(RsgradError::MissingGradient)
+    }
+
+    pub fn zero_grad(&self) {
+        self.0.borrow_mut().grad = None;
+    }
+
+    pub fn detach(&self) -> Var<T> {
+        Var::new(self.0.borrow().detach())
+    }
+
+    /// Backpropagates from this node, seeding it with ones.
+    pub fn backward(&self) {
+        let seed = Tensor::constant_fill(T::one(), &self.shape());
+        self.0.borrow_mut().backward(Rc::new(RefCell::new(seed)));
+    }
+
+    /// Backpropagates `grad` from this node, see [`Tensor::backward_with`].
+    pub fn backward_with(&self, grad: &Var<T>, create_graph: bool) {
+        self.0.borrow_mut().backward_with(grad.0.clone(), create_graph);
+    }
+
+    pub fn matmul(&self, other: &Var<T>) -> Var<T> {
+        Var::new(ops::MatMul::forward(self.0.clone(), other.0.clone()))
+    }
+
+    pub fn relu(&self) -> Var<T> {
+        Var::new(ops::Relu::forward(self.0.clone()))
+    }
+
+    pub fn log(&self) -> Var<T> {
+        Var::new(ops::Log::forward(self.0.clone()))
+    }
+
+    pub fn exp(&self) -> Var<T> {
+        Var::new(ops::Exp::forward(self.0.clone()))
+    }
+
+    pub fn sqrt(&self) -> Var<T> {
+        Var::new(ops::Sqrt::forward(self.0.clone()))
+    }
+
+    pub fn pow(&self, exponent: &Var<T>) -> Var<T> {
+        Var::new(ops::Pow::forward(self.0.clone(), exponent.0.clone()))
+    }
+
+    pub fn powf(&self, exponent: f64) -> Var<T> {
+        Var::new(ops::PowScalar::forward(self.0.clone(), exponent))
+    }
+
+    pub fn abs(&self) -> Var<T> {
+        Var::new(ops::Abs::forward(self.0.clone()))
+    }
+
+    pub fn sin(&self) -> Var<T> {
+        Var::new(ops::Sin::forward(self.0.clone()))
+    }
+
+    pub fn cos(&self) -> Var<T> {
+        Var::new(ops::Cos::forward(self.0.clone()))
+    }
+
+    pub fn tanh(&self) -> Var<T> {
+        Var::new(ops::Tanh::forward(self.0.clone()))
+    }
+
+    pub fn sigmoid(&self) -> Var<T> {
+        Var::new(ops::Sigmoid::forward(self.0.clone()))
+    }
+
+    pub fn clamp(&self, min: f64, max: f64) -> Var<T> {
+        Var::new(ops::Clamp::forward(self.0.clone(), min, max))
+    }
+
+    pub fn reciprocal(&self) -> Var<T> {
+        Var::new(ops::Reciprocal::forward(self.0.clone()))
+    }
+
+    pub fn maximum(&self, other: &Var<T>) -> Var<T> {
+        Var::new(ops::Maximum::forward(self.0.clone(), other.0.clone()))
+    }
+
+    pub fn minimum(&self, other: &Var<T>) -> Var<T> {
+        Var::new(ops::Minimum::forward(self.0.clone(), other.0.clone()))
+    }
+
+    pub fn l2norm(&self) -> Var<T> {
+        Var::new(ops::L2norm::forward(self.0.clone()))
+    }
+
+    pub fn sum(&self) -> Var<T> {
+        Var::new(ops::Sum::forward(self.0.clone()))
+    }
+
+    pub fn sum_dim(&self, dims: &[usize], keepdim: bool) -> Var<T> {
+        Var::new(ops::SumDim::forward(self.0.clone(), dims, keepdim))
+    }
+
+    pub fn mean(&self, dims: &[usize], keepdim: bool) -> Var<T> {
+        Var::new(ops::Mean::forward(self.0.clone(), dims, keepdim))
+    }
+
+    pub fn max(&self, dims: &[usize], keepdim: bool) -> Var<T> {
+        Var::new(ops::Max::forward(self.0.clone(), dims, keepdim))
+    }
+
+    pub fn min(&self, dims: &[usize], keepdim: bool) -> Var<T> {
+        Var::new(ops::Min::forward(self.0.clone(), dims, keepdim))
+    }
+
+    pub fn prod(&self, dims: &[usize], keepdim: bool) -> Var<T> {
+        Var::new(ops::Prod::forward(self.0.clone(), dims, keepdim))
+    }
+
+    pub fn transpose(&self) -> Var<T> {
+        Var::new(ops::Transpose::forward(self.0.clone()))
+    }
+
+    pub fn reshape(&self, shape: &[u32]) -> Var<T> {
+        Var::new(ops::Reshape::forward(self.0.clone(), shape))
+    }
+
+    pub fn permute(&self, dims: &[usize]) -> Var<T> {
+        Var::new(ops::Permute::forward(self.0.clone(), dims))
+    }
+
+    pub fn expand(&self, shape: &[u32]) -> Var<T> {
+        Var::new(ops::Expand::forward(self.0.clone(), shape))
+    }
+
+    pub fn squeeze(&self, dim: usize) -> Var<T> {
+        Var::new(ops::Squeeze::forward(self.0.clone(), dim))
+    }
+
+    pub fn unsqueeze(&self, dim: usize) -> Var<T> {
+        Var::new(ops::Unsqueeze::forward(self.0.clone(), dim))
+    }
+
+    pub fn slice(&self, dim: usize, start: u32, end: u32, step: u32) -> Var<T> {
+        Var::new(ops::Slice::forward(self.0.clone(), dim, start, end, step))
+    }
+
+    pub fn index_select(&self, dim: usize, index: &[u32]) -> Var<T> {
+        Var::new(ops::IndexSelect::forward(self.0.clone(), dim, index))
+    }
+
+    pub fn gather(&self, dim: usize, index: &Tensor<i64>) -> Var<T> {
+        Var::new(ops::Gather::forward(self.0.clone(), dim, index))
+    }
+
+    pub fn scatter_add(&self, dim: usize, index: &Tensor<i64>, src: &Var<T>) -> Var<T> {
+        Var::new(ops::ScatterAdd::forward(self.0.clone(), dim, index, src.0.clone()))
+    }
+
+    pub fn masked_select(&self, mask: &Tensor<bool>) -> Var<T> {
+        Var::new(ops::MaskedSelect::forward(self.0.clone(), mask))
+    }
+
+    pub fn masked_fill(&self, mask: &Tensor<bool>, value: &Var<T>) -> Var<T> {
+        Var::new(ops::MaskedFill::forward(self.0.clone(), mask, value.0.clone()))
+    }
+
+    pub fn sort(&self, dim: usize, descending: bool) -> (Var<T>, Tensor<i64>) {
+        let (values, indices) = ops::sort(self.0.clone(), dim, descending);
+        (Var::new(values), indices)
+    }
+
+    pub fn topk(&self, k: u32, dim: usize, largest: bool) -> (Var<T>, Tensor<i64>) {
+        let (values, indices) = ops::topk(self.0.clone(), k, dim, largest);
+        (Var::new(values), indices)
+    }
+
+    pub fn cumsum(&self, dim: usize) -> Var<T> {
+        Var::new(ops::CumSum::forward(self.0.clone(), dim, false))
+    }
+
+    pub fn cumprod(&self, dim: usize) -> Var<T> {
+        Var::new(ops::CumProd::forward(self.0.clone(), dim))
+    }
+
+    pub fn logcumsumexp(&self, dim: usize) -> Var<T> {
+        Var::new(ops::LogCumSumExp::forward(self.0.clone(), dim))
+    }
+
+    pub fn split(&self, dim: usize, sizes: &[u32]) -> Vec<Var<T>> {
+        ops::split(self.0.clone(), dim, sizes).into_iter().map(Var::new).collect()
+    }
+
+    pub fn chunk(&self, dim: usize, chunks: u32) -> Vec<Var<T>> {
+        ops::chunk(self.0.clone(), dim, chunks).into_iter().map(Var::new).collect()
+    }
+
+    pub fn cat(vars: &[Var<T>], dim: usize) -> Var<T> {
+        Var::new(ops::Cat::forward(vars.iter().map(|var| var.0.clone()).collect(), dim))
+    }
+
+    pub fn stack(vars: &[Var<T>], dim: usize) -> Var<T> {
+        Var::new(ops::Stack::forward(vars.iter().map(|var| var.0.clone()).collect(), dim))
+    }
+
+    /// See [`einsum::einsum`](crate::einsum::einsum).
+    pub fn einsum(equation: &str, vars: &[Var<T>]) -> Var<T> {
+        Var::new(einsum::einsum(equation, vars.iter().map(|var| var.0.clone()).collect()))
+    }
+
+    pub fn where_cond(cond: &Tensor<bool>, on_true: &Var<T>, on_false: &Var<T>) -> Var<T> {
+        Var::new(ops::Where::forward(cond, on_true.0.clone(), on_false.0.clone()))
+    }
+}
+
+impl<T: Float> From<Tensor<T>> for Var<T> {
+    fn from(tensor: Tensor<T>) -> Var<T> {
+        Var::new(tensor)
+    }
+}
+
+impl<T: Float> From<Rc<RefCell<Tensor<T>>>> for Var<T> {
+    fn from(node: Rc<RefCell<Tensor<T>>>) -> Var<T> {
+        Var(node)
+    }
+}
+
+/// Implements a binary operator for every mix of owned and borrowed handles and scalars of
+/// the element type.
+macro_rules! binary_op {
+    ($trait:ident, $method:ident, $op:ident) => {
+        impl<T: Float> std::ops::$trait<&Var<T>> for &Var<T> {
+            type Output = Var<T>;
+
+            fn $method(self, other: &Var<T>) -> Var<T> {
+                Var::new(ops::$op::forward(self.0.clone(), other.0.clone()))
+            }
+        }
+
+        impl<T: Float> std::ops::$trait<Var<T>> for Var<T> {
+            type Output = Var<T>;
+
+            fn $method(self, other: Var<T>) -> Var<T> {
+                std::ops::$trait::$method(&self, &other)
+            }
+        }
+
+        impl<T: Float> std::ops::$trait<&Var<T>> for Var<T> {
+            type Output = Var<T>;
+
+            fn $method(self, other: &Var<T>) -> Var<T> {
+                std::ops::$trait::$method(&self, other)
+            }
+        }
+
+        impl<T: Float> std::ops::$trait<Var<T>> for &Var<T> {
+            type Output = Var<T>;
+
+            fn $method(self, other: Var<T>) -> Var<T> {
+                std::ops::$trait::$method(self, &other)
+            }
+        }
+
+        impl<T: Float> std::ops::$trait<T> for &Var<T> {
+            type Output = Var<T>;
+
+            fn $method(self, other: T) -> Var<T> {
+                std::ops::$trait::$method(self, &Var::scalar(other))
+            }
+        }
+
+        impl<T: Float> std::ops::$trait<T> for Var<T> {
+            type Output = Var<T>;
+
+            fn $method(self, other: T) -> Var<T> {
+                std::ops::$trait::$method(&self, &Var::scalar(other))
+            }
+        }
+
+        scalar_lhs_op!($trait, $method, f32);
+        scalar_lhs_op!($trait, $method, f64);
+    };
+}
+
+/// Scalars on the left need an impl per type, a blanket one over `T` is not allowed.
+macro_rules! scalar_lhs_op {
+    ($trait:ident, $method:ident, $scalar:ty) => {
+        impl std::ops::$trait<&Var<$scalar>> for $scalar {
+            type Output = Var<$scalar>;
+
+            fn $method(self, other: &Var<$scalar>) -> Var<$scalar> {
+                std::ops::$trait::$method(&Var::scalar(self), other)
+            }
+        }
+
+        impl std::ops::$trait<Var<$scalar>> for $scalar {
+            type Output = Var<$scalar>;
+
+            fn $method(self, other: Var<$scalar>) -> Var<$scalar> {
+                std::ops::$trait::$method(&Var::scalar(self), &other)
+            }
+        }
+    };
+}
+
+binary_op!(Add, add, Add);
+binary_op!(Sub, sub, Sub);
+binary_op!(Mul, mul, Mult);
+binary_op!(Div, div, Div);
+
+impl<T: Float> std::ops::Neg for &Var<T> {
+    type Output = Var<T>;
+
+    fn neg(self) -> Var<T> {
+        Var::new(ops::Neg::forward(self.0.clone()))
+    }
+}
+
+impl<T: Float> std::ops::Neg for Var<T> {
+    type Output = Var<T>;
+
+    fn neg(self) -> Var<T> {
+        -&self
+    }
+}